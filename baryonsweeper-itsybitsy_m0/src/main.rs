@@ -1,7 +1,7 @@
 #![no_std]
 #![no_main]
 
-use baryonsweeper::BaryonSweeper;
+use baryonsweeper::{BaryonSweeper, BatteryProfile, EmulatedEeprom, Responder, Timeout as _};
 use panic_rtt_target as _;
 use rtt_target::rtt_init_print;
 use itsybitsy_m0 as bsp;
@@ -11,6 +11,7 @@ use bsp::pac;
 use bsp::entry;
 
 use hal::clock::GenericClockController;
+use hal::delay::Delay;
 use hal::prelude::*;
 use hal::timer::TimerCounter;
 use hal::usb::UsbBus;
@@ -43,7 +44,7 @@ fn main() -> ! {
     let gclk0 = clocks.gclk0();
     let tc45 = &clocks.tc4_tc5(&gclk0).unwrap();
     // instantiate a timer objec for the TC4 peripheral
-    let timer = TimerCounter::tc4_(tc45, peripherals.TC4, &mut pm);
+    let mut timer = TimerCounter::tc4_(tc45, peripherals.TC4, &mut pm);
 
 
     // Take peripheral and pins
@@ -51,7 +52,7 @@ fn main() -> ! {
     let uart_rx = pins.d0;
     let uart_tx = pins.d1;
 
-    let uart = bsp::uart(
+    let mut uart = bsp::uart(
         &mut clocks,
         19200.hz(),
         uart_sercom,
@@ -88,21 +89,43 @@ fn main() -> ! {
         NVIC::unmask(interrupt::USB);
     }
 
-    let led_pin: bsp::RedLed = pins.d13.into();
+    let mut led_pin: bsp::RedLed = pins.d13.into();
+    let mut delay = Delay::new(core.SYST, &mut clocks);
 
 
     let usb_serial = unsafe { USB_SERIAL.as_mut().unwrap() };
     // FIXME
     let _logger = embedded_logger::UsbLogger::<UsbBus,256>::new(usb_serial);
 
-    let timeout: hal::time::Nanoseconds = 500.ms().into();
-    let mut baryon_sweeper = BaryonSweeper::new(uart, timer, led_pin, timeout);
+    let responder = Responder::new(EmulatedEeprom::default(), BatteryProfile::default());
+    let mut baryon_sweeper = BaryonSweeper::new(&mut uart, &mut timer, &mut led_pin, Timeout::millis(500), &mut delay, responder);
     baryon_sweeper.sweep();
     core::unreachable!()
 
 
 }
 
+/// Sweeper timeouts for the TC4 `CountDown`, which counts in this HAL's
+/// own nanosecond type. Saturates at about 4.29 s.
+#[derive(Clone, Copy)]
+struct Timeout(hal::time::Nanoseconds);
+
+impl baryonsweeper::Timeout for Timeout {
+    fn millis(ms: u32) -> Self {
+        Timeout(hal::time::Nanoseconds(ms.saturating_mul(1_000_000)))
+    }
+
+    fn to_millis(&self) -> u32 {
+        self.0 .0 / 1_000_000
+    }
+}
+
+impl From<Timeout> for hal::time::Nanoseconds {
+    fn from(timeout: Timeout) -> Self {
+        timeout.0
+    }
+}
+
 static mut USB_ALLOCATOR: Option<UsbBusAllocator<UsbBus>> = None;
 static mut USB_BUS: Option<UsbDevice<UsbBus>> = None;
 static mut USB_SERIAL: Option<SerialPort<UsbBus>> = None;
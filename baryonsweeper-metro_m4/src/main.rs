@@ -1,7 +1,7 @@
 #![no_std]
 #![no_main]
 
-use baryonsweeper::BaryonSweeper;
+use baryonsweeper::{BaryonSweeper, BatteryProfile, EmulatedEeprom, Responder};
 use panic_rtt_target as _;
 use rtt_target::rtt_init_print;
 use metro_m4 as bsp;
@@ -12,6 +12,7 @@ use bsp::entry;
 use bsp::{pin_alias, periph_alias};
 
 use hal::clock::GenericClockController;
+use hal::delay::Delay;
 use hal::prelude::*;
 use hal::timer::TimerCounter;
 use hal::usb::UsbBus;
@@ -41,13 +42,13 @@ fn main() -> ! {
     
     let gclk0 = clocks.gclk0();
     let tc2_3 = clocks.tc2_tc3(&gclk0).unwrap();
-    let timer = TimerCounter::tc3_(&tc2_3, peripherals.TC3, &mut peripherals.MCLK);
+    let mut timer = TimerCounter::tc3_(&tc2_3, peripherals.TC3, &mut peripherals.MCLK);
 
     let uart_rx = pin_alias!(pins.uart_rx);
     let uart_tx = pin_alias!(pins.uart_tx);
     let uart_sercom = periph_alias!(peripherals.uart_sercom);
 
-    let uart = bsp::uart(
+    let mut uart = bsp::uart(
         &mut clocks,
         19200.Hz(),
         uart_sercom,
@@ -89,12 +90,14 @@ fn main() -> ! {
         core.NVIC.set_priority(interrupt::USB_OTHER, 1);
         NVIC::unmask(interrupt::USB_OTHER);
     }
-    let led_pin: bsp::RedLed = pins.d13.into();
+    let mut led_pin: bsp::RedLed = pins.d13.into();
+    let mut delay = Delay::new(core.SYST, &mut clocks);
 
     //let usb_serial = unsafe { USB_SERIAL.as_mut().unwrap() };
     // FIXME
     //let _logger = embedded_logger::CombinedLogger::<UsbBus,256>::new(usb_serial);
-    let mut baryon_sweeper = BaryonSweeper::new(uart, timer, led_pin, 500.millis());
+    let responder = Responder::new(EmulatedEeprom::default(), BatteryProfile::default());
+    let mut baryon_sweeper = BaryonSweeper::new(&mut uart, &mut timer, &mut led_pin, 500.millis(), &mut delay, responder);
     baryon_sweeper.sweep();
     core::unreachable!()
 
@@ -25,7 +25,7 @@ use bsp::hal::{
     usb::UsbBus,
 };
 
-use baryonsweeper::BaryonSweeper;
+use baryonsweeper::{BaryonSweeper, BatteryProfile, EmulatedEeprom, Responder};
 
 // USB Device support
 #[cfg(feature="usb")]
@@ -151,7 +151,8 @@ fn main() -> ! {
         unsafe { let _ = log::set_logger_racy( LOGGER.as_ref().unwrap() ).map(|()| log::set_max_level_racy(LevelFilter::Debug)); }
     }
     
-    let mut baryon_sweeper = BaryonSweeper::new(&mut uart, &mut timer, &mut led_pin, 500.millis(), &mut delay) ;
+    let responder = Responder::new(EmulatedEeprom::default(), BatteryProfile::default());
+    let mut baryon_sweeper = BaryonSweeper::new(&mut uart, &mut timer, &mut led_pin, 500.millis(), &mut delay, responder) ;
     defmt::println!("Starting Sweep!");
 
     baryon_sweeper.sweep();
@@ -1,3 +1,328 @@
+//! Battery emulator on a Linux host, e.g. a Raspberry Pi UART or a USB-UART
+//! adapter wired to the battery connector.
+
+use std::{env, fs, process, time::{Duration, Instant}};
+use std::io::{ErrorKind, Write};
+
+use baryonsweeper::{
+    BaryonSweeper, BatteryModel, BatteryProfile, BatteryRecorder, EmulatedEeprom, Error, Frame, Frames,
+    Request, Responder, EEPROM_SIZE,
+};
+use embedded_hal::{digital::v2::OutputPin, serial};
+use embedded_logger::StdLogger;
+use linux_embedded_hal::{Delay, Serial, SysTimer, SysfsPin};
+use serial_core::{BaudRate, CharSize, FlowControl, Parity, PortSettings, SerialPort, StopBits};
+use sysfs_gpio::Direction;
+
+use pty::PtyMaster;
+
+mod pty;
+
+const USAGE: &str = "\
+Usage: baryonsweeper-rpi_linux [options] <device>
+       baryonsweeper-rpi_linux [options] --pty
+
+Emulate a PSP battery on the serial port <device>, e.g. /dev/ttyAMA0, or
+on a new pseudo-terminal whose path is printed on startup.
+
+With --record, ask a genuine battery on the port what syscon would and
+save its identity and EEPROM to <file> instead, for --profile <file>.
+
+Options, in any order:
+    --timeout <ms>     longest gap between two bytes of a packet [500]
+    --profile <name>   a preset or a file saved with --record [{default}]
+                       presets: {presets}
+    --record <file>    record the battery on the port to <file>
+    --serial <hex>     serial number as stored in the pack, 8 hex digits,
+                       e.g. 12345678 [the profile's]
+    --led <gpio>       sysfs GPIO number of an activity LED
+    -h, --help         print this help";
+
+/// The help, listing the presets `--profile` takes.
+fn usage() -> String {
+    let presets: Vec<_> = BatteryModel::ALL.iter().map(|model| model.name()).collect();
+    let default = BatteryModel::ALL
+        .into_iter()
+        .find(|model| model.profile() == BatteryProfile::default())
+        .map_or("", BatteryModel::name);
+    USAGE.replace("{presets}", &presets.join(", ")).replace("{default}", default)
+}
+
+/// Syscon talks 19200 baud, 8 data bits, even parity, one stop bit.
+const SYSCON_SERIAL: PortSettings = PortSettings {
+    baud_rate: BaudRate::Baud19200,
+    char_size: CharSize::Bits8,
+    parity: Parity::ParityEven,
+    stop_bits: StopBits::Stop1,
+    flow_control: FlowControl::FlowNone,
+};
+
+/// Where syscon is.
+enum Port {
+    Device(String),
+    Pty,
+}
+
+struct Options {
+    port: Port,
+    timeout: Duration,
+    profile: BatteryProfile,
+    eeprom: EmulatedEeprom,
+    record: Option<String>,
+    led: Option<u64>,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut device = None;
+    let mut pty = false;
+    let mut timeout = Duration::from_millis(500);
+    let mut profile = BatteryProfile::default();
+    let mut eeprom = EmulatedEeprom::default();
+    let mut serial_number = None;
+    let mut record = None;
+    let mut led = None;
+
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or(format!("{} needs a value", name));
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{}", usage());
+                process::exit(0);
+            },
+            "--timeout" => {
+                let ms = value("--timeout")?;
+                let ms = ms.parse().map_err(|_| format!("invalid timeout {}", ms))?;
+                timeout = Duration::from_millis(ms);
+            },
+            "--profile" => {
+                let name = value("--profile")?;
+                (profile, eeprom) = match BatteryModel::from_name(&name) {
+                    Some(model) => (model.profile(), EmulatedEeprom::default()),
+                    None => load_recording(&name)?,
+                };
+            },
+            "--record" => record = Some(value("--record")?),
+            "--serial" => {
+                let hex = value("--serial")?;
+                serial_number = Some(parse_serial_number(&hex).ok_or(format!("invalid serial number {}", hex))?);
+            },
+            "--pty" => pty = true,
+            "--led" => {
+                let gpio = value("--led")?;
+                led = Some(gpio.parse().map_err(|_| format!("invalid GPIO {}", gpio))?);
+            },
+            option if option.starts_with('-') => return Err(format!("unknown option {}", option)),
+            _ if device.is_some() => return Err(format!("unexpected argument {}", arg)),
+            _ => device = Some(arg),
+        }
+    }
+
+    let port = match (device, pty) {
+        (Some(device), false) => Port::Device(device),
+        (None, true) => Port::Pty,
+        (Some(_), true) => return Err("give either a serial device or --pty".into()),
+        (None, false) => return Err("no serial device given".into()),
+    };
+    if let Some(serial_number) = serial_number {
+        profile = profile.with_serial_number(serial_number);
+    }
+    Ok(Options { port, timeout, profile, eeprom, record, led })
+}
+
+/// The profile and EEPROM image saved by `--record` to `path`.
+fn load_recording(path: &str) -> Result<(BatteryProfile, EmulatedEeprom), String> {
+    let bytes = fs::read(path).map_err(|e| match e.kind() {
+        ErrorKind::NotFound => format!("unknown profile {}", path),
+        _ => format!("cannot read {}: {}", path, e),
+    })?;
+    let recorder = BatteryRecorder::from_bytes(&bytes).map_err(|e| format!("cannot load {}: {}", path, e))?;
+    Ok((*recorder.profile(), EmulatedEeprom::new(*recorder.eeprom().contents())))
+}
+
+/// Eight hex digits, most significant byte first.
+fn parse_serial_number(hex: &str) -> Option<[u8; 4]> {
+    if hex.len() != 8 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None
+    }
+    u32::from_str_radix(hex, 16).ok().map(u32::to_be_bytes)
+}
+
+/// Activity LED on a sysfs GPIO, or nothing.
+enum Led {
+    Gpio(SysfsPin),
+    None,
+}
+
+impl Led {
+    fn open(gpio: Option<u64>) -> sysfs_gpio::Result<Self> {
+        let Some(gpio) = gpio else {
+            return Ok(Led::None)
+        };
+        let pin = SysfsPin::new(gpio);
+        pin.0.export()?;
+        pin.0.set_direction(Direction::High)?;
+        Ok(Led::Gpio(pin))
+    }
+}
+
+impl OutputPin for Led {
+    type Error = sysfs_gpio::Error;
+
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        match self {
+            Led::Gpio(pin) => pin.set_low(),
+            Led::None => Ok(()),
+        }
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        match self {
+            Led::Gpio(pin) => pin.set_high(),
+            Led::None => Ok(()),
+        }
+    }
+}
+
+fn open_serial(device: &str) -> serial_core::Result<Serial> {
+    let mut serial = Serial::open(device)?;
+    serial.0.configure(&SYSCON_SERIAL)?;
+    // return from reads right away, the sweeper's timer decides how long to wait
+    serial.0.set_timeout(Duration::from_millis(1))?;
+    Ok(serial)
+}
+
 fn main() {
-    println!("Hello, world!");
+    let options = parse_args(env::args().skip(1)).unwrap_or_else(|e| {
+        eprintln!("{}\n\n{}", e, usage());
+        process::exit(2);
+    });
+    let _ = StdLogger::init();
+
+    match &options.port {
+        Port::Device(device) => {
+            let mut serial = open_serial(device).unwrap_or_else(|e| {
+                eprintln!("Cannot open {}: {}", device, e);
+                process::exit(1);
+            });
+            run(&mut serial, &options);
+        },
+        Port::Pty => {
+            let (mut master, path) = PtyMaster::open().unwrap_or_else(|e| {
+                eprintln!("Cannot create a pseudo-terminal: {}", e);
+                process::exit(1);
+            });
+            match options.record {
+                Some(_) => println!("Recording the battery on {}", path),
+                None => println!("Virtual battery on {}", path),
+            }
+            let _ = std::io::stdout().flush();
+            run(&mut master, &options);
+        },
+    }
+}
+
+fn run<S: serial::Read<u8> + serial::Write<u8>>(serial: &mut S, options: &Options) {
+    match &options.record {
+        Some(path) => record(serial, path, options.timeout),
+        None => sweep(serial, options),
+    }
+}
+
+fn sweep<S: serial::Read<u8> + serial::Write<u8>>(serial: &mut S, options: &Options) {
+    let mut led_pin = Led::open(options.led).unwrap_or_else(|e| {
+        eprintln!("Cannot set up the LED: {}", e);
+        process::exit(1);
+    });
+    let mut timer = SysTimer::new();
+    let mut delay = Delay;
+
+    let responder = Responder::new(EmulatedEeprom::new(*options.eeprom.contents()), options.profile);
+    let mut baryon_sweeper = BaryonSweeper::new(serial, &mut timer, &mut led_pin, options.timeout, &mut delay, responder);
+    baryon_sweeper.sweep();
+}
+
+/// What syscon asks a battery besides the handshake, in the order it asks.
+const RECORDED: [Request; 11] = [
+    Request::ReadStatus,
+    Request::ReadSerialno,
+    Request::ReadTemperature,
+    Request::ReadVoltage,
+    Request::ReadCurrent,
+    Request::ReadCapacity,
+    Request::Read8,
+    Request::ReadTimeLeft,
+    Request::Read11,
+    Request::Read13,
+    Request::Read22,
+];
+
+/// Play syscon to the battery on `serial`, then read its whole EEPROM, and
+/// save what it answered to `path`.
+fn record<S: serial::Read<u8> + serial::Write<u8>>(serial: &mut S, path: &str, timeout: Duration) {
+    let mut recorder = BatteryRecorder::new();
+    let eeprom = (0..EEPROM_SIZE as u8).map(|address| Request::ReadEeprom { address });
+    for request in RECORDED.into_iter().chain(eeprom) {
+        let Ok(frame) = request.to_frame() else {
+            continue
+        };
+        match exchange(serial, &frame, timeout).and_then(|responses| recorder.record_exchange(&frame, &responses)) {
+            Ok(()) => {},
+            Err(e @ (Error::TimedOut | Error::Serial)) => {
+                eprintln!("No answer to {:?}: {}", request, e);
+                process::exit(1);
+            },
+            Err(e) => eprintln!("Not recorded, {:?}: {}", request, e),
+        }
+    }
+
+    fs::write(path, recorder.to_bytes()).unwrap_or_else(|e| {
+        eprintln!("Cannot write {}: {}", path, e);
+        process::exit(1);
+    });
+    let eeprom_bytes = (0..EEPROM_SIZE as u8).filter(|&address| recorder.is_eeprom_recorded(address)).count();
+    println!(
+        "Recorded {} to {}, {} of {} EEPROM bytes",
+        if recorder.has_identity() { "the identity" } else { "part of the identity" },
+        path,
+        eeprom_bytes,
+        EEPROM_SIZE,
+    );
+}
+
+/// Send `request` and read the answer, each byte within `timeout`.
+fn exchange<S: serial::Read<u8> + serial::Write<u8>>(serial: &mut S, request: &Frame, timeout: Duration) -> Result<Frames, Error> {
+    for byte in request.as_bytes() {
+        nb::block!(serial.write(*byte)).map_err(|_| Error::Serial)?;
+    }
+    nb::block!(serial.flush()).map_err(|_| Error::Serial)?;
+
+    let mut bytes = [0u8; 64];
+    let mut len = 0;
+    let mut deadline = Instant::now() + timeout;
+    loop {
+        match serial.read() {
+            // anything before the header is line noise
+            Ok(byte) if len == 0 && byte != 0xa5 => {},
+            Ok(byte) => {
+                bytes[len] = byte;
+                len += 1;
+                deadline = Instant::now() + timeout;
+            },
+            Err(nb::Error::WouldBlock) if Instant::now() >= deadline => return Err(Error::TimedOut),
+            Err(nb::Error::WouldBlock) => {},
+            Err(nb::Error::Other(_)) => return Err(Error::Serial),
+        }
+        if len < 2 {
+            continue
+        }
+        let length = bytes[1] as usize;
+        if !(2..=bytes.len() - 2).contains(&length) {
+            return Err(Error::BadLength)
+        }
+        if len == length + 2 {
+            let mut responses = Frames::new();
+            responses.push(Frame::from_bytes(&bytes[..len])?);
+            return Ok(responses)
+        }
+    }
 }
@@ -0,0 +1,93 @@
+//! Virtual battery on a pseudo-terminal, for testing without hardware.
+
+use std::ffi::CStr;
+use std::fs::File;
+use std::io::{self, ErrorKind, Read, Write};
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+
+/// Sweeper side of a pty pair. Syscon, e.g. a test harness, opens the
+/// slave device returned by `open` like any serial port.
+pub struct PtyMaster {
+    master: File,
+    /// Kept open so the slave keeps its raw settings and reads on the
+    /// master do not fail while nothing is attached.
+    _slave: File,
+}
+
+impl PtyMaster {
+    /// Create a pty pair, returning the master and the slave's path.
+    pub fn open() -> io::Result<(Self, String)> {
+        let master = check(unsafe { libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY) })?;
+        let master = unsafe { File::from_raw_fd(master) };
+        check(unsafe { libc::grantpt(master.as_raw_fd()) })?;
+        check(unsafe { libc::unlockpt(master.as_raw_fd()) })?;
+
+        let mut name = [0 as libc::c_char; 64];
+        let error = unsafe { libc::ptsname_r(master.as_raw_fd(), name.as_mut_ptr(), name.len()) };
+        if error != 0 {
+            return Err(io::Error::from_raw_os_error(error))
+        }
+        let path = unsafe { CStr::from_ptr(name.as_ptr()) }.to_string_lossy().into_owned();
+
+        let slave = check(unsafe { libc::open(name.as_ptr(), libc::O_RDWR | libc::O_NOCTTY) })?;
+        let slave = unsafe { File::from_raw_fd(slave) };
+        make_raw(slave.as_raw_fd())?;
+
+        Ok((Self { master, _slave: slave }, path))
+    }
+
+    /// Wait up to a millisecond for input, so the sweeper's polling loop
+    /// does not spin.
+    fn wait_readable(&self) -> io::Result<bool> {
+        let mut fd = libc::pollfd { fd: self.master.as_raw_fd(), events: libc::POLLIN, revents: 0 };
+        let ready = check(unsafe { libc::poll(&mut fd, 1, 1) })?;
+        Ok(ready > 0)
+    }
+}
+
+fn check(result: libc::c_int) -> io::Result<libc::c_int> {
+    if result < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(result)
+    }
+}
+
+/// No echo, no line editing, no newline translation: bytes go through as
+/// they are, like on a UART.
+fn make_raw(fd: RawFd) -> io::Result<()> {
+    let mut termios = unsafe { std::mem::zeroed::<libc::termios>() };
+    check(unsafe { libc::tcgetattr(fd, &mut termios) })?;
+    unsafe { libc::cfmakeraw(&mut termios) };
+    check(unsafe { libc::tcsetattr(fd, libc::TCSANOW, &termios) })?;
+    Ok(())
+}
+
+impl embedded_hal::serial::Read<u8> for PtyMaster {
+    type Error = ErrorKind;
+
+    fn read(&mut self) -> nb::Result<u8, Self::Error> {
+        if !self.wait_readable().map_err(|e| e.kind())? {
+            return Err(nb::Error::WouldBlock)
+        }
+        let mut byte = [0u8; 1];
+        match self.master.read(&mut byte) {
+            Ok(1) => Ok(byte[0]),
+            Ok(_) => Err(nb::Error::WouldBlock),
+            Err(e) if e.kind() == ErrorKind::Interrupted => Err(nb::Error::WouldBlock),
+            Err(e) => Err(nb::Error::Other(e.kind())),
+        }
+    }
+}
+
+impl embedded_hal::serial::Write<u8> for PtyMaster {
+    type Error = ErrorKind;
+
+    fn write(&mut self, byte: u8) -> nb::Result<(), Self::Error> {
+        self.master.write_all(&[byte]).map_err(|e| nb::Error::Other(e.kind()))
+    }
+
+    fn flush(&mut self) -> nb::Result<(), Self::Error> {
+        self.master.flush().map_err(|e| nb::Error::Other(e.kind()))
+    }
+}
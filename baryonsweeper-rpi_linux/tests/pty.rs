@@ -0,0 +1,189 @@
+//! End-to-end tests against the binary serving a virtual battery on a pty.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use std::process::{Child, Command, Stdio};
+use std::thread;
+
+use baryonsweeper::{BatteryModel, BatteryProfile, BatteryRecorder, Error, Frame, Frames, SysconEmulator, EEPROM_SIZE};
+
+/// The binary running with `--pty`, killed when dropped.
+struct VirtualBattery {
+    child: Child,
+    path: String,
+    port: File,
+}
+
+impl VirtualBattery {
+    fn spawn(args: &[&str]) -> Self {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_baryonsweeper-rpi_linux"))
+            .args(args)
+            .arg("--pty")
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let mut stdout = BufReader::new(child.stdout.take().unwrap());
+        let mut line = String::new();
+        let path = loop {
+            line.clear();
+            assert!(stdout.read_line(&mut line).unwrap() > 0, "no pty path printed");
+            if let Some(path) = line.trim().strip_prefix("Virtual battery on ") {
+                break path.to_owned()
+            }
+        };
+        // keep the log flowing so the sweeper never blocks on stdout
+        thread::spawn(move || std::io::copy(&mut stdout, &mut std::io::sink()));
+
+        let port = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_NOCTTY)
+            .open(&path)
+            .unwrap();
+        Self { child, path, port }
+    }
+
+    fn send(&mut self, bytes: &[u8]) {
+        self.port.write_all(bytes).unwrap();
+    }
+
+    /// Everything received until the line has been quiet for `quiet_ms`.
+    fn receive(&mut self, quiet_ms: i32) -> Vec<u8> {
+        let mut received = Vec::new();
+        loop {
+            let mut fd = libc::pollfd { fd: self.port.as_raw_fd(), events: libc::POLLIN, revents: 0 };
+            if unsafe { libc::poll(&mut fd, 1, quiet_ms) } <= 0 {
+                return received
+            }
+            let mut buf = [0u8; 64];
+            let n = self.port.read(&mut buf).unwrap();
+            received.extend_from_slice(&buf[..n]);
+        }
+    }
+
+    fn exchange(&mut self, request: &Frame, responses: &mut Frames) -> Result<(), Error> {
+        self.send(request.as_bytes());
+        let received = self.receive(100);
+        responses.clear();
+        let mut rest = &received[..];
+        while let [_, length, ..] = *rest {
+            let (frame, tail) = rest.split_at((length as usize + 2).min(rest.len()));
+            responses.push(Frame::from_bytes(frame)?);
+            rest = tail;
+        }
+        if responses.is_empty() {
+            return Err(Error::TimedOut)
+        }
+        Ok(())
+    }
+}
+
+impl Drop for VirtualBattery {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Counts up, the challenges do not need to be random here.
+struct CountingRng(u8);
+
+impl rand_core::RngCore for CountingRng {
+    fn next_u32(&mut self) -> u32 {
+        rand_core::impls::next_u32_via_fill(self)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        rand_core::impls::next_u64_via_fill(self)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for byte in dest {
+            self.0 = self.0.wrapping_add(1);
+            *byte = self.0;
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand_core::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+#[test]
+fn test_syscon_polls_virtual_battery() {
+    let mut battery = VirtualBattery::spawn(&["--profile", "go"]);
+    let mut syscon = SysconEmulator::new(0xEB).unwrap().with_auth_go(true);
+    let polled = syscon.poll(&mut CountingRng(0), |request, responses| battery.exchange(request, responses));
+    // not the default pack `poll` starts from, so the preset made it onto the wire
+    assert_ne!(BatteryProfile::GO, BatteryProfile::default());
+    assert_eq!(polled, Ok(BatteryProfile::GO));
+    assert!(syscon.is_authenticated());
+}
+
+#[test]
+fn test_serial_number_option() {
+    let mut battery = VirtualBattery::spawn(&["--serial", "12345678", "--profile", "slim-1200"]);
+    let mut syscon = SysconEmulator::new(0xEB).unwrap().with_auth_go(true);
+    let polled = syscon.poll(&mut CountingRng(0), |request, responses| battery.exchange(request, responses));
+    assert_eq!(polled, Ok(BatteryProfile::SLIM_1200MAH.with_serial_number([0x12, 0x34, 0x56, 0x78])));
+}
+
+#[test]
+fn test_virtual_battery_recovers_from_line_errors() {
+    let mut battery = VirtualBattery::spawn(&["--timeout", "50"]);
+
+    // cut off mid packet: no answer, and the next packet is read afresh
+    battery.send(&[0x5a, 0x05, 0x01]);
+    assert_eq!(battery.receive(200), []);
+    battery.send(&[0x5a, 0x02, 0x01, 0xa2]);
+    assert_eq!(battery.receive(100), [0xa5, 0x05, 0x06, 0x10, 0xc3, 0x06, 0x76]);
+
+    // bad checksum
+    battery.send(&[0x5a, 0x02, 0x01, 0xa3]);
+    assert_eq!(battery.receive(100), [0xa5, 0x02, 0x05, 0x53]);
+}
+
+#[test]
+fn test_record_and_replay() {
+    let dir = std::env::temp_dir();
+    let source = dir.join(format!("baryonsweeper-source-{}.bin", std::process::id()));
+    let recording = dir.join(format!("baryonsweeper-recording-{}.bin", std::process::id()));
+
+    // a pack no preset matches, with an EEPROM the default is not
+    let profile = BatteryProfile::GO.with_serial_number([0x87, 0x65, 0x43, 0x21]);
+    let mut bytes = BatteryRecorder::with_profile(profile).to_bytes();
+    let len = bytes.len();
+    for (i, byte) in bytes[len - EEPROM_SIZE..].iter_mut().enumerate() {
+        *byte = i as u8 ^ 0x5a;
+    }
+    fs::write(&source, bytes).unwrap();
+
+    let battery = VirtualBattery::spawn(&["--profile", source.to_str().unwrap()]);
+    let status = Command::new(env!("CARGO_BIN_EXE_baryonsweeper-rpi_linux"))
+        .args(["--record", recording.to_str().unwrap(), &battery.path])
+        .stdout(Stdio::null())
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let recorded = BatteryRecorder::from_bytes(&fs::read(&recording).unwrap()).unwrap();
+    let _ = fs::remove_file(&source);
+    let _ = fs::remove_file(&recording);
+    assert!(recorded.has_identity());
+    assert!(recorded.is_eeprom_complete());
+    assert_eq!(*recorded.profile(), profile);
+    assert_eq!(recorded.eeprom().contents()[..], bytes[len - EEPROM_SIZE..]);
+}
+
+#[test]
+fn test_help_lists_every_preset() {
+    let output = Command::new(env!("CARGO_BIN_EXE_baryonsweeper-rpi_linux")).arg("--help").output().unwrap();
+    let help = String::from_utf8(output.stdout).unwrap();
+    for model in BatteryModel::ALL {
+        assert!(help.contains(model.name()), "{} missing from\n{}", model.name(), help);
+    }
+    assert!(help.contains("[slim-1800]"));
+}
@@ -0,0 +1,78 @@
+//! Handshake latency of `Responder`. "handshake" reuses one responder, as
+//! the firmware does, so the AES key schedules are already cached;
+//! "handshake from power on" pays for expanding them first.
+//!
+//! The "aes" cases isolate the eight AES blocks of a handshake, four
+//! encrypted with the challenge key and two decrypted with each Go key, and
+//! show what the cache saves over expanding the key for every block.
+
+use aes::Aes128;
+use aes::cipher::{BlockDecrypt, BlockEncrypt, KeyInit, generic_array::GenericArray};
+use baryonsweeper::{BatteryProfile, BuiltinKeys, EmulatedEeprom, Frames, KeyStore, Responder};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+// captured from a PSP-1000 with challenge version 0xD9
+const AUTH1: [u8; 13] = [0x5A, 0x0B, 0x80, 0xD9, 0x8E, 0x35, 0xF3, 0x8F, 0x2B, 0x8C, 0x6D, 0x8F, 0x49];
+const AUTH2: [u8; 12] = [0x5A, 0x0A, 0x81, 0x13, 0xF1, 0x06, 0x0B, 0x97, 0x9E, 0x9F, 0xF9, 0x38];
+const AUTH_GO: [u8; 44] = [
+    0x5A, 0x2A, 0x90, 0x20, 0x10, 0x00, 0x06, 0x82, 0x82, 0x82, 0x82, 0xCB, 0xA3, 0xDB, 0xAC, 0x00,
+    0xDF, 0x26, 0xF8, 0xDD, 0x5B, 0x0D, 0xAC, 0x91, 0x9A, 0xCF, 0x0B, 0x63, 0x26, 0x06, 0x18, 0xE6,
+    0x30, 0x4F, 0xDF, 0xE1, 0x6C, 0xEE, 0xA5, 0x16, 0x4E, 0x94, 0x15, 0xED,
+];
+
+fn handshake<E: baryonsweeper::Eeprom>(responder: &mut Responder<E>, responses: &mut Frames) {
+    for request in [&AUTH1[..], &AUTH2, &AUTH_GO] {
+        responder.respond_packet(black_box(request), responses).unwrap();
+    }
+}
+
+/// The AES work of one handshake, each block with its own key schedule.
+fn aes_uncached(key: &[u8; 16], go_key1: &[u8; 16], go_key2: &[u8; 16]) {
+    let mut block = GenericArray::from([0u8; 16]);
+    for _ in 0..4 {
+        Aes128::new(&GenericArray::from(*key)).encrypt_block(black_box(&mut block));
+    }
+    for go_key in [go_key1, go_key1, go_key2, go_key2] {
+        Aes128::new(&GenericArray::from(*go_key)).decrypt_block(black_box(&mut block));
+    }
+}
+
+/// The AES work of one handshake with the schedules already expanded.
+fn aes_cached(cipher: &Aes128, go_request: &Aes128, go_response: &Aes128) {
+    let mut block = GenericArray::from([0u8; 16]);
+    for _ in 0..4 {
+        cipher.encrypt_block(black_box(&mut block));
+    }
+    for go in [go_request, go_request, go_response, go_response] {
+        go.decrypt_block(black_box(&mut block));
+    }
+}
+
+fn bench_handshake(c: &mut Criterion) {
+    let mut responses = Frames::new();
+
+    let mut responder = Responder::new(EmulatedEeprom::default(), BatteryProfile::default());
+    c.bench_function("handshake", |b| b.iter(|| handshake(&mut responder, &mut responses)));
+
+    c.bench_function("handshake from power on", |b| b.iter(|| {
+        let mut responder = Responder::new(EmulatedEeprom::default(), BatteryProfile::default());
+        handshake(&mut responder, &mut responses)
+    }));
+
+    c.bench_function("auth1", |b| b.iter(|| {
+        responder.respond_packet(black_box(&AUTH1), &mut responses).unwrap()
+    }));
+
+    let keys = BuiltinKeys;
+    let key = keys.key(AUTH1[3]).unwrap();
+    let (go_key1, go_key2) = (keys.go_key1(), keys.go_key2());
+    c.bench_function("aes, key schedule per block", |b| b.iter(|| aes_uncached(&key, &go_key1, &go_key2)));
+
+    let cipher = Aes128::new(&GenericArray::from(key));
+    let go_request = Aes128::new(&GenericArray::from(go_key1));
+    let go_response = Aes128::new(&GenericArray::from(go_key2));
+    c.bench_function("aes, cached key schedules", |b| b.iter(|| aes_cached(&cipher, &go_request, &go_response)));
+}
+
+criterion_group!(benches, bench_handshake);
+criterion_main!(benches);
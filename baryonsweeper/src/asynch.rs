@@ -0,0 +1,157 @@
+//! Async driver for embedded-io-async, e.g. on Embassy.
+//!
+//! Produces the same responses as the blocking drivers but yields while
+//! waiting for syscon, so USB, LEDs and the battery protocol can run as
+//! separate tasks. The timeouts race the pending reads against a tick of
+//! an async `DelayNs`; the UART read must therefore be safe to cancel, as
+//! the Embassy UART drivers are. Every byte restarts the tick, so the
+//! inter-byte timeout counts from the last byte; the battery simulation
+//! advances by the ticks that ran out, which leaves out the part tick each
+//! byte cut short and the time spent writing answers.
+
+use core::pin::pin;
+
+use embassy_futures::select::{select, Either};
+use embedded_hal_1::digital::OutputPin;
+use embedded_hal_async::delay::DelayNs;
+use embedded_io_async::{Read, Write};
+use fugit::MicrosDurationU32;
+
+use crate::{
+    fmt_packet, BatterySensors, BuiltinKeys, ChecksumPolicy, Dispatcher, Eeprom, Error,
+    FrameReader, FrameWait, Frames, KeyStore, NoSensors, Reply, Responder, TICK_MS,
+};
+
+use ufmt::uWrite;
+
+use log::{info, debug};
+
+pub struct BaryonSweeper<'a, S, P, D, E, K = BuiltinKeys, B = NoSensors>
+where
+    S: Read + Write,
+    P: OutputPin,
+    D: DelayNs,
+    E: Eeprom,
+    K: KeyStore,
+    B: BatterySensors,
+{
+    serial: &'a mut S,
+    led_pin: &'a mut P,
+    timeout: MicrosDurationU32,
+    delay: &'a mut D,
+    dispatcher: Dispatcher<E, K, B>,
+}
+
+impl<'a, S, P, D, E, K, B> BaryonSweeper<'a, S, P, D, E, K, B>
+where
+    S: Read + Write,
+    P: OutputPin,
+    D: DelayNs,
+    E: Eeprom,
+    K: KeyStore,
+    B: BatterySensors,
+{
+    /// `timeout` is the longest gap allowed between two bytes of a packet.
+    pub fn new(serial: &'a mut S, led_pin: &'a mut P, timeout: MicrosDurationU32, delay: &'a mut D, responder: Responder<E, K, B>) -> Self {
+        Self {
+            serial,
+            led_pin,
+            timeout,
+            delay,
+            dispatcher: Dispatcher::new(responder),
+        }
+    }
+
+    pub fn responder(&self) -> &Responder<E, K, B> {
+        &self.dispatcher.responder
+    }
+
+    pub fn responder_mut(&mut self) -> &mut Responder<E, K, B> {
+        &mut self.dispatcher.responder
+    }
+
+    pub fn set_checksum_policy(&mut self, policy: ChecksumPolicy) {
+        self.dispatcher.checksum_policy = policy;
+    }
+
+    /// Number of received packets dropped because of a bad checksum.
+    pub fn checksum_errors(&self) -> u32 {
+        self.dispatcher.checksum_errors
+    }
+
+    async fn receive_packet(&mut self, recv: &mut [u8; 64], len: &mut u8) -> Result<(), Error> {
+        let mut reader = FrameReader::new();
+        let mut waited = 0u32;
+        info!("Waiting for 5a");
+        loop {
+            let wait = reader.wait();
+            let mut byte = [0u8; 1];
+            let tick = pin!(self.delay.delay_ms(TICK_MS));
+            let read = match select(self.serial.read(&mut byte), tick).await {
+                Either::First(Ok(1)) => Some(Ok(byte[0])),
+                Either::First(_) => Some(Err(Error::Serial)),
+                Either::Second(()) => None,
+            };
+            // a byte restarts the tick along with the inter-byte timeout
+            if let Some(read) = read {
+                waited = 0;
+                if let Some(result) = reader.step(wait, read) {
+                    reader.finish(recv, len);
+                    return result
+                }
+                continue
+            }
+
+            self.dispatcher.responder.advance(TICK_MS);
+            waited = waited.saturating_add(TICK_MS);
+            let wait = reader.wait();
+            if wait == FrameWait::InterByte && waited >= self.timeout.to_millis() {
+                reader.step(wait, Err(Error::TimedOut));
+                reader.finish(recv, len);
+                return Err(Error::TimedOut)
+            }
+        }
+    }
+
+    async fn send_packet(&mut self, packet: &[u8]) {
+        let mut msg = heapless::String::<2048>::new();
+        let _ = ufmt::uwrite!(msg, "Sending packet: ");
+        let _ = msg.write_str(fmt_packet(packet, packet.len()).as_str());
+        debug!("{}", msg.as_str());
+
+        let _ = self.serial.write_all(packet).await;
+        let _ = self.serial.flush().await;
+    }
+
+    async fn send_frames(&mut self, responses: &Frames) {
+        for frame in responses.iter() {
+            self.send_packet(frame).await;
+        }
+    }
+
+    pub async fn sweep(&mut self) -> ! {
+        info!("Beginning the sweep!");
+        loop {
+            if let Err(e) = self.sweep_iter().await {
+                info!("Sweep error: {}", e);
+            }
+        }
+    }
+
+    pub async fn sweep_iter(&mut self) -> Result<(), Error> {
+        let mut recv = [0u8; 64];
+        let mut length = 0u8;
+        let received = self.receive_packet(&mut recv, &mut length).await;
+        let mut responses = Frames::new();
+        let result = match self.dispatcher.dispatch(received.map(|()| &recv[..length as usize]), &mut responses) {
+            Reply::Send(result) => result,
+            Reply::Drop(e) => return Err(e),
+        };
+        self.led_pin.set_low().map_err(|_| Error::Pin)?;
+        self.send_frames(&responses).await;
+        self.led_pin.set_high().map_err(|_| Error::Pin)?;
+        self.delay.delay_ms(1).await;
+        self.dispatcher.responder.advance(1);
+        result
+    }
+}
@@ -0,0 +1,29 @@
+/// Progress of the battery authentication handshake.
+///
+/// Syscon always authenticates in the order `CmdAuth1`, `CmdAuth2` and,
+/// on a PSP Go, `CmdAuthGo`. A `CmdAuth1` restarts the handshake from any
+/// state; any other authentication command that arrives out of order is
+/// answered with a NAK and leaves the state untouched.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AuthState {
+    #[default]
+    Idle,
+    Auth1Done {
+        version: u8,
+        challenge1b: [u8; 16],
+    },
+    Auth2Done {
+        version: u8,
+    },
+    GoDone,
+}
+
+impl AuthState {
+    /// Challenge version negotiated by the last `CmdAuth1`, if any.
+    pub fn version(&self) -> Option<u8> {
+        match self {
+            AuthState::Auth1Done { version, .. } | AuthState::Auth2Done { version } => Some(*version),
+            AuthState::Idle | AuthState::GoDone => None,
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use aes::Aes128;
+use aes::cipher::{BlockDecrypt, BlockEncrypt, KeyInit, generic_array::GenericArray};
+
+use crate::{Error, KeyStore};
+
+struct GoCiphers {
+    /// `GO_KEY1`, decrypts the `CmdAuthGo` request.
+    request: Aes128,
+    /// `GO_KEY2`, produces the `CmdAuthGo` response.
+    response: Aes128,
+}
+
+/// A key store together with the AES key schedules expanded from it.
+///
+/// Expanding a key costs more than the single block encryption that uses
+/// it, so the schedule of the last challenge version and the `CmdAuthGo`
+/// schedules are built lazily and kept between requests.
+pub(crate) struct Ciphers<K: KeyStore> {
+    keys: K,
+    version: Option<(u8, Aes128)>,
+    go: Option<GoCiphers>,
+}
+
+impl<K: KeyStore> Ciphers<K> {
+    pub(crate) fn new(keys: K) -> Self {
+        Self {
+            keys,
+            version: None,
+            go: None,
+        }
+    }
+
+    pub(crate) fn keys(&self) -> &K {
+        &self.keys
+    }
+
+    fn cipher(&mut self, version: u8) -> Result<&Aes128, Error> {
+        let stale = !matches!(self.version, Some((cached, _)) if cached == version);
+        if stale {
+            let key = self.keys.key(version).ok_or(Error::UnknownChallengeVersion(version))?;
+            self.version = Some((version, Aes128::new(&GenericArray::from(key))));
+        }
+        self.version.as_ref().map(|(_, cipher)| cipher).ok_or(Error::UnknownChallengeVersion(version))
+    }
+
+    fn go(&mut self) -> &GoCiphers {
+        let keys = &self.keys;
+        self.go.get_or_insert_with(|| GoCiphers {
+            request: Aes128::new(&GenericArray::from(keys.go_key1())),
+            response: Aes128::new(&GenericArray::from(keys.go_key2())),
+        })
+    }
+
+    /// Encrypt one block with the key of `version`.
+    pub(crate) fn encrypt(&mut self, version: u8, plain: &[u8; 16]) -> Result<[u8; 16], Error> {
+        let mut block = GenericArray::from(*plain);
+        self.cipher(version)?.encrypt_block(&mut block);
+        Ok(block.into())
+    }
+
+    pub(crate) fn go_decrypt_request(&mut self, blocks: [[u8; 16]; 2]) -> [[u8; 16]; 2] {
+        cbc_decrypt(&self.go().request, blocks)
+    }
+
+    pub(crate) fn go_decrypt_response(&mut self, blocks: [[u8; 16]; 2]) -> [[u8; 16]; 2] {
+        cbc_decrypt(&self.go().response, blocks)
+    }
+}
+
+/// AES-CBC decryption with a zero IV.
+fn cbc_decrypt(cipher: &Aes128, blocks: [[u8; 16]; 2]) -> [[u8; 16]; 2] {
+    let mut previous = [0u8; 16];
+    blocks.map(|encrypted| {
+        let mut block = GenericArray::from(encrypted);
+        cipher.decrypt_block(&mut block);
+        let mut plain: [u8; 16] = block.into();
+        for (byte, iv) in plain.iter_mut().zip(previous) {
+            *byte ^= iv;
+        }
+        previous = encrypted;
+        plain
+    })
+}
@@ -0,0 +1,271 @@
+//! Typed view of the packets on the wire.
+//!
+//! `Request` covers the 0x5A packets syscon sends, `Response` the 0xA5
+//! answers. A response carries no command byte, so it is decoded against
+//! the request it answers.
+
+use core::convert::{TryFrom, TryInto};
+
+use crate::{
+    build_packet, build_request, verify_checksum, Commands, Error, Frame, ResponseType,
+    MANUFACTURER_MAX_LEN, MAX_PAYLOAD,
+};
+
+/// Arguments or payload of a packet this codec has no type for.
+pub type RawPayload = heapless::Vec<u8, MAX_PAYLOAD>;
+
+/// A request from syscon.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Request {
+    ReadStatus,
+    ReadTemperature,
+    ReadVoltage,
+    ReadCurrent,
+    ReadCapacity,
+    Read8,
+    ReadTimeLeft,
+    Read11,
+    ReadSerialno,
+    Read13,
+    WriteEeprom { address: u8, value: u8 },
+    ReadEeprom { address: u8 },
+    Read22,
+    Auth1 { version: u8, challenge: [u8; 8] },
+    /// Eight bytes the console derives from the battery's `Auth1` answer
+    /// and keys, without a version byte.
+    Auth2 { challenge: [u8; 8] },
+    /// Fixed header followed by two AES-CBC blocks.
+    AuthGo { data: [u8; 40] },
+    /// A command byte without a known meaning.
+    Other { command: u8, args: RawPayload },
+}
+
+impl Request {
+    pub fn command(&self) -> u8 {
+        let command = match self {
+            Request::ReadStatus => Commands::CmdReadStatus,
+            Request::ReadTemperature => Commands::CmdReadTemperature,
+            Request::ReadVoltage => Commands::CmdReadVoltage,
+            Request::ReadCurrent => Commands::CmdReadCurrent,
+            Request::ReadCapacity => Commands::CmdReadCapacity,
+            Request::Read8 => Commands::CmdRead8,
+            Request::ReadTimeLeft => Commands::CmdReadTimeLeft,
+            Request::Read11 => Commands::CmdRead11,
+            Request::ReadSerialno => Commands::CmdReadSerialno,
+            Request::Read13 => Commands::CmdRead13,
+            Request::WriteEeprom { .. } => Commands::CmdWriteEeprom,
+            Request::ReadEeprom { .. } => Commands::CmdReadEeprom,
+            Request::Read22 => Commands::CmdRead22,
+            Request::Auth1 { .. } => Commands::CmdAuth1,
+            Request::Auth2 { .. } => Commands::CmdAuth2,
+            Request::AuthGo { .. } => Commands::CmdAuthGo,
+            Request::Other { command, .. } => return *command,
+        };
+        command as u8
+    }
+
+    /// Parse a whole 0x5A packet, checksum included.
+    pub fn from_frame(packet: &[u8]) -> Result<Self, Error> {
+        let body = framed(0x5a, packet)?;
+        let (&command, args) = body.split_first().ok_or(Error::BadLength)?;
+        let Ok(known) = Commands::try_from(command) else {
+            return Ok(Request::Other { command, args: raw(args)? })
+        };
+        let request = match (known, args) {
+            (Commands::CmdReadStatus, []) => Request::ReadStatus,
+            (Commands::CmdReadTemperature, []) => Request::ReadTemperature,
+            (Commands::CmdReadVoltage, []) => Request::ReadVoltage,
+            (Commands::CmdReadCurrent, []) => Request::ReadCurrent,
+            (Commands::CmdReadCapacity, []) => Request::ReadCapacity,
+            (Commands::CmdRead8, []) => Request::Read8,
+            (Commands::CmdReadTimeLeft, []) => Request::ReadTimeLeft,
+            (Commands::CmdRead11, []) => Request::Read11,
+            (Commands::CmdReadSerialno, []) => Request::ReadSerialno,
+            (Commands::CmdRead13, []) => Request::Read13,
+            (Commands::CmdWriteEeprom, &[address, value]) => Request::WriteEeprom { address, value },
+            (Commands::CmdReadEeprom, &[address]) => Request::ReadEeprom { address },
+            (Commands::CmdRead22, []) => Request::Read22,
+            (Commands::CmdAuth1, [version, challenge @ ..]) => Request::Auth1 {
+                version: *version,
+                challenge: fixed(challenge, Error::BadLength)?,
+            },
+            (Commands::CmdAuth2, challenge) => Request::Auth2 { challenge: fixed(challenge, Error::BadLength)? },
+            (Commands::CmdAuthGo, data) => Request::AuthGo { data: fixed(data, Error::BadLength)? },
+            _ => return Err(Error::BadLength),
+        };
+        Ok(request)
+    }
+
+    pub fn to_frame(&self) -> Result<Frame, Error> {
+        let mut args = [0u8; 40];
+        let args: &[u8] = match self {
+            Request::WriteEeprom { address, value } => {
+                args[..2].copy_from_slice(&[*address, *value]);
+                &args[..2]
+            },
+            Request::ReadEeprom { address } => {
+                args[0] = *address;
+                &args[..1]
+            },
+            Request::Auth1 { version, challenge } => {
+                args[0] = *version;
+                args[1..9].copy_from_slice(challenge);
+                &args[..9]
+            },
+            Request::Auth2 { challenge } => challenge,
+            Request::AuthGo { data } => data,
+            Request::Other { args, .. } => args,
+            _ => &[],
+        };
+        let (bytes, len) = build_request(self.command(), args)?;
+        Ok(Frame::new(bytes, len))
+    }
+}
+
+/// A battery's answer.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Response {
+    Nak,
+    /// Acknowledges `WriteEeprom`.
+    Ack,
+    Status([u8; 3]),
+    /// In °C.
+    Temperature(u8),
+    /// In mV.
+    Voltage(u16),
+    /// In mA.
+    Current(i16),
+    /// Remaining capacity in mAh.
+    Capacity(u16),
+    Read8(u16),
+    /// In minutes.
+    TimeLeft(u16),
+    Read11(u16),
+    /// In storage order, the wire has both 16-bit halves byte swapped.
+    Serialno([u8; 4]),
+    Read13([u8; 5]),
+    Eeprom(u8),
+    /// Manufacturer name.
+    Read22(heapless::Vec<u8, MANUFACTURER_MAX_LEN>),
+    Auth1([u8; 16]),
+    /// The short `Auth1` answer to a challenge version the battery does
+    /// not know, all 0xFF from our responder.
+    Auth1Refused([u8; 8]),
+    Auth2([u8; 16]),
+    AuthGo([u8; 40]),
+    /// Acknowledged payload of a `Request::Other`.
+    Other(RawPayload),
+}
+
+impl Response {
+    /// Parse a whole 0xA5 packet answering `request`.
+    pub fn from_frame(request: &Request, packet: &[u8]) -> Result<Self, Error> {
+        let body = framed(0xa5, packet)?;
+        let (&code, payload) = body.split_first().ok_or(Error::BadLength)?;
+        match ResponseType::try_from(code) {
+            Ok(ResponseType::Nak) if payload.is_empty() => return Ok(Response::Nak),
+            Ok(ResponseType::Ack) => {},
+            _ => return Err(Error::BadResponse),
+        }
+
+        let word = || fixed(payload, Error::BadResponse).map(u16::from_le_bytes);
+        let response = match request {
+            Request::ReadStatus => Response::Status(fixed(payload, Error::BadResponse)?),
+            Request::ReadTemperature => {
+                let [temperature] = fixed(payload, Error::BadResponse)?;
+                Response::Temperature(temperature)
+            },
+            Request::ReadVoltage => Response::Voltage(word()?),
+            Request::ReadCurrent => Response::Current(i16::from_le_bytes(fixed(payload, Error::BadResponse)?)),
+            Request::ReadCapacity => Response::Capacity(word()?),
+            Request::Read8 => Response::Read8(word()?),
+            Request::ReadTimeLeft => Response::TimeLeft(word()?),
+            Request::Read11 => Response::Read11(word()?),
+            Request::ReadSerialno => {
+                let [b1, b0, b3, b2] = fixed(payload, Error::BadResponse)?;
+                Response::Serialno([b0, b1, b2, b3])
+            },
+            Request::Read13 => Response::Read13(fixed(payload, Error::BadResponse)?),
+            Request::WriteEeprom { .. } if payload.is_empty() => Response::Ack,
+            Request::WriteEeprom { .. } => return Err(Error::BadResponse),
+            Request::ReadEeprom { .. } => {
+                let [value] = fixed(payload, Error::BadResponse)?;
+                Response::Eeprom(value)
+            },
+            Request::Read22 => Response::Read22(payload.try_into().map_err(|_| Error::BadResponse)?),
+            Request::Auth1 { .. } if payload.len() == 8 => Response::Auth1Refused(fixed(payload, Error::BadResponse)?),
+            Request::Auth1 { .. } => Response::Auth1(fixed(payload, Error::BadResponse)?),
+            Request::Auth2 { .. } => Response::Auth2(fixed(payload, Error::BadResponse)?),
+            Request::AuthGo { .. } => Response::AuthGo(fixed(payload, Error::BadResponse)?),
+            Request::Other { .. } => Response::Other(raw(payload)?),
+        };
+        Ok(response)
+    }
+
+    pub fn to_frame(&self) -> Result<Frame, Error> {
+        let mut buf = [0u8; 40];
+        let payload: &[u8] = match self {
+            Response::Nak => {
+                let (bytes, len) = build_packet(ResponseType::Nak as u8, &[])?;
+                return Ok(Frame::new(bytes, len))
+            },
+            Response::Ack => &[],
+            Response::Status(status) => status,
+            Response::Temperature(temperature) => {
+                buf[0] = *temperature;
+                &buf[..1]
+            },
+            Response::Voltage(word)
+            | Response::Capacity(word)
+            | Response::Read8(word)
+            | Response::TimeLeft(word)
+            | Response::Read11(word) => {
+                buf[..2].copy_from_slice(&word.to_le_bytes());
+                &buf[..2]
+            },
+            Response::Current(current) => {
+                buf[..2].copy_from_slice(&current.to_le_bytes());
+                &buf[..2]
+            },
+            Response::Serialno([b0, b1, b2, b3]) => {
+                buf[..4].copy_from_slice(&[*b1, *b0, *b3, *b2]);
+                &buf[..4]
+            },
+            Response::Read13(read13) => read13,
+            Response::Eeprom(value) => {
+                buf[0] = *value;
+                &buf[..1]
+            },
+            Response::Read22(manufacturer) => manufacturer,
+            Response::Auth1(answer) | Response::Auth2(answer) => answer,
+            Response::Auth1Refused(answer) => answer,
+            Response::AuthGo(answer) => answer,
+            Response::Other(payload) => payload,
+        };
+        let (bytes, len) = build_packet(ResponseType::Ack as u8, payload)?;
+        Ok(Frame::new(bytes, len))
+    }
+}
+
+/// Check the header, length and checksum of `packet` and return what is
+/// between the length byte and the checksum.
+fn framed(header: u8, packet: &[u8]) -> Result<&[u8], Error> {
+    let [received_header, length, body @ ..] = packet else {
+        return Err(Error::BadLength)
+    };
+    if *received_header != header || *length < 2 || body.len() != *length as usize {
+        return Err(Error::BadLength)
+    }
+    if !verify_checksum(header, *length, body) {
+        return Err(Error::Checksum)
+    }
+    Ok(&body[..body.len() - 1])
+}
+
+fn fixed<const N: usize>(bytes: &[u8], error: Error) -> Result<[u8; N], Error> {
+    bytes.try_into().map_err(|_| error)
+}
+
+fn raw(bytes: &[u8]) -> Result<RawPayload, Error> {
+    bytes.try_into().map_err(|_| Error::BadLength)
+}
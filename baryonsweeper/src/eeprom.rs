@@ -0,0 +1,82 @@
+/// Size of the battery EEPROM as seen by syscon, in bytes.
+pub const EEPROM_SIZE: usize = 0x80;
+
+/// Backing store for `CmdReadEeprom` / `CmdWriteEeprom`.
+///
+/// Syscon addresses the battery EEPROM one byte at a time, with addresses
+/// in `0x00..0x80`. Implementations return `None` for addresses they do
+/// not back, which is answered with a NAK just like a genuine pack does.
+pub trait Eeprom {
+    fn read(&self, address: u8) -> Option<u8>;
+    fn write(&mut self, address: u8, value: u8) -> Option<()>;
+}
+
+/// RAM backed EEPROM image.
+pub struct EmulatedEeprom {
+    contents: [u8; EEPROM_SIZE],
+}
+
+impl EmulatedEeprom {
+    pub fn new(contents: [u8; EEPROM_SIZE]) -> Self {
+        Self { contents }
+    }
+
+    /// An erased EEPROM, every byte reads back as 0xFF.
+    pub fn erased() -> Self {
+        Self::new([0xFF; EEPROM_SIZE])
+    }
+
+    pub fn contents(&self) -> &[u8; EEPROM_SIZE] {
+        &self.contents
+    }
+
+    pub fn contents_mut(&mut self) -> &mut [u8; EEPROM_SIZE] {
+        &mut self.contents
+    }
+
+    /// Read a little endian 16-bit word starting at `address`.
+    pub fn read_word(&self, address: u8) -> Option<u16> {
+        let lo = self.read(address)?;
+        let hi = self.read(address.checked_add(1)?)?;
+        Some(u16::from_le_bytes([lo, hi]))
+    }
+
+    /// Write a little endian 16-bit word starting at `address`.
+    pub fn write_word(&mut self, address: u8, value: u16) -> Option<()> {
+        let hi_address = address.checked_add(1)?;
+        if hi_address as usize >= EEPROM_SIZE {
+            return None;
+        }
+        let [lo, hi] = value.to_le_bytes();
+        self.write(address, lo)?;
+        self.write(hi_address, hi)
+    }
+}
+
+impl Default for EmulatedEeprom {
+    fn default() -> Self {
+        Self::erased()
+    }
+}
+
+impl Eeprom for EmulatedEeprom {
+    fn read(&self, address: u8) -> Option<u8> {
+        self.contents.get(address as usize).copied()
+    }
+
+    fn write(&mut self, address: u8, value: u8) -> Option<()> {
+        let byte = self.contents.get_mut(address as usize)?;
+        *byte = value;
+        Some(())
+    }
+}
+
+impl<E: Eeprom + ?Sized> Eeprom for &mut E {
+    fn read(&self, address: u8) -> Option<u8> {
+        (**self).read(address)
+    }
+
+    fn write(&mut self, address: u8, value: u8) -> Option<()> {
+        (**self).write(address, value)
+    }
+}
@@ -0,0 +1,155 @@
+//! Driver for embedded-hal 1.0 and embedded-io.
+//!
+//! embedded-hal 1.0 dropped the `CountDown` timer, so timeouts are counted
+//! by polling `ReadReady` and sleeping on the `DelayNs` in between. The
+//! battery simulation is told about every sleep; the time spent writing
+//! answers is not counted.
+
+use embedded_hal_1::{delay::DelayNs, digital::OutputPin};
+use embedded_io::{Read, ReadReady, Write};
+use fugit::MicrosDurationU32;
+
+use crate::{
+    fmt_packet, receive_frame, BatterySensors, BuiltinKeys, ChecksumPolicy, Dispatcher, Eeprom,
+    Error, FrameWait, Frames, KeyStore, NoSensors, Reply, Responder, TICK_MS,
+};
+
+use ufmt::uWrite;
+
+use log::{info, debug};
+
+/// Time between two `read_ready` polls while waiting for a byte.
+const POLL_INTERVAL_US: u32 = 50;
+
+pub struct BaryonSweeper<'a, S, P, D, E, K = BuiltinKeys, B = NoSensors>
+where
+    S: Read + ReadReady + Write,
+    P: OutputPin,
+    D: DelayNs,
+    E: Eeprom,
+    K: KeyStore,
+    B: BatterySensors,
+{
+    serial: &'a mut S,
+    led_pin: &'a mut P,
+    timeout: MicrosDurationU32,
+    delay: &'a mut D,
+    dispatcher: Dispatcher<E, K, B>,
+    /// Time slept that the simulation has not been told about, in µs.
+    slept_us: u32,
+}
+
+impl<'a, S, P, D, E, K, B> BaryonSweeper<'a, S, P, D, E, K, B>
+where
+    S: Read + ReadReady + Write,
+    P: OutputPin,
+    D: DelayNs,
+    E: Eeprom,
+    K: KeyStore,
+    B: BatterySensors,
+{
+    /// `timeout` is the longest gap allowed between two bytes of a packet.
+    pub fn new(serial: &'a mut S, led_pin: &'a mut P, timeout: MicrosDurationU32, delay: &'a mut D, responder: Responder<E, K, B>) -> Self {
+        Self {
+            serial,
+            led_pin,
+            timeout,
+            delay,
+            dispatcher: Dispatcher::new(responder),
+            slept_us: 0,
+        }
+    }
+
+    pub fn responder(&self) -> &Responder<E, K, B> {
+        &self.dispatcher.responder
+    }
+
+    pub fn responder_mut(&mut self) -> &mut Responder<E, K, B> {
+        &mut self.dispatcher.responder
+    }
+
+    pub fn set_checksum_policy(&mut self, policy: ChecksumPolicy) {
+        self.dispatcher.checksum_policy = policy;
+    }
+
+    /// Number of received packets dropped because of a bad checksum.
+    pub fn checksum_errors(&self) -> u32 {
+        self.dispatcher.checksum_errors
+    }
+
+    /// Sleep, handing the time to the simulation a tick at a time.
+    fn sleep_us(&mut self, us: u32) {
+        self.delay.delay_us(us);
+        self.slept_us = self.slept_us.saturating_add(us);
+        if self.slept_us >= TICK_MS * 1000 {
+            let ms = self.slept_us / 1000;
+            self.dispatcher.responder.advance(ms);
+            self.slept_us -= ms * 1000;
+        }
+    }
+
+    /// Wait for the next byte. There is no limit on the wait for a header,
+    /// a byte inside a packet must come within `timeout`.
+    fn read_byte(&mut self, wait: FrameWait) -> Result<u8, Error> {
+        let mut waited = 0u32;
+        loop {
+            if self.serial.read_ready().map_err(|_| Error::Serial)? {
+                let mut byte = [0u8; 1];
+                return match self.serial.read(&mut byte) {
+                    Ok(1) => Ok(byte[0]),
+                    _ => Err(Error::Serial),
+                }
+            }
+            if wait == FrameWait::InterByte && waited >= self.timeout.to_micros() {
+                return Err(Error::TimedOut)
+            }
+            self.sleep_us(POLL_INTERVAL_US);
+            waited = waited.saturating_add(POLL_INTERVAL_US);
+        }
+    }
+
+    fn receive_packet(&mut self, recv: &mut [u8; 64], len: &mut u8) -> Result<(), Error> {
+        receive_frame(recv, len, |wait| self.read_byte(wait))
+    }
+
+    fn send_packet(&mut self, packet: &[u8]) {
+        let mut msg = heapless::String::<2048>::new();
+        let _ = ufmt::uwrite!(msg, "Sending packet: ");
+        let _ = msg.write_str(fmt_packet(packet, packet.len()).as_str());
+        debug!("{}", msg.as_str());
+
+        let _ = self.serial.write_all(packet);
+        let _ = self.serial.flush();
+    }
+
+    fn send_frames(&mut self, responses: &Frames) {
+        for frame in responses.iter() {
+            self.send_packet(frame);
+        }
+    }
+
+    pub fn sweep(&mut self) {
+        info!("Beginning the sweep!");
+        loop {
+            if let Err(e) = self.sweep_iter() {
+                info!("Sweep error: {}", e);
+            }
+        }
+    }
+
+    pub fn sweep_iter(&mut self) -> Result<(), Error> {
+        let mut recv = [0u8; 64];
+        let mut length = 0u8;
+        let received = self.receive_packet(&mut recv, &mut length);
+        let mut responses = Frames::new();
+        let result = match self.dispatcher.dispatch(received.map(|()| &recv[..length as usize]), &mut responses) {
+            Reply::Send(result) => result,
+            Reply::Drop(e) => return Err(e),
+        };
+        self.led_pin.set_low().map_err(|_| Error::Pin)?;
+        self.send_frames(&responses);
+        self.led_pin.set_high().map_err(|_| Error::Pin)?;
+        self.sleep_us(1000);
+        result
+    }
+}
@@ -0,0 +1,52 @@
+use core::fmt;
+
+/// Errors returned by the sweeper and the battery protocol.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The serial port reported an error.
+    Serial,
+    /// No byte arrived before the timeout expired.
+    TimedOut,
+    /// The LED pin could not be driven.
+    Pin,
+    /// No key material is known for this challenge version.
+    UnknownChallengeVersion(u8),
+    /// A packet or its payload has the wrong length.
+    BadLength,
+    /// The packet checksum does not match its contents.
+    Checksum,
+    /// The `CmdAuthGo` request did not decrypt to the expected secret.
+    GoValidation,
+    /// An authentication command arrived out of order.
+    AuthOrder,
+    /// The EEPROM address is outside of the emulated EEPROM.
+    EepromAddress(u8),
+    /// The battery answered with a NAK.
+    Nak,
+    /// A battery response failed verification.
+    BadResponse,
+    /// Bytes that are not a recording in a format this version reads.
+    BadRecording,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Serial => write!(f, "serial error"),
+            Error::TimedOut => write!(f, "timed out"),
+            Error::Pin => write!(f, "LED pin error"),
+            Error::UnknownChallengeVersion(version) => write!(f, "unknown challenge version 0x{:02x}", version),
+            Error::BadLength => write!(f, "bad length"),
+            Error::Checksum => write!(f, "checksum mismatch"),
+            Error::GoValidation => write!(f, "invalid CmdAuthGo request"),
+            Error::AuthOrder => write!(f, "authentication command out of order"),
+            Error::EepromAddress(address) => write!(f, "EEPROM address 0x{:02x} out of range", address),
+            Error::Nak => write!(f, "NAK from battery"),
+            Error::BadResponse => write!(f, "invalid battery response"),
+            Error::BadRecording => write!(f, "not a battery recording"),
+        }
+    }
+}
+
+#[cfg(feature="std")]
+impl std::error::Error for Error {}
@@ -0,0 +1,232 @@
+use crate::consts::{GO_KEY1, GO_KEY2, GO_SECRET, KEYRING};
+
+/// Key material for the challenge-response handshake.
+///
+/// Lookups return `None` for challenge versions the store does not know,
+/// which is answered the way a battery without that key would.
+pub trait KeyStore {
+    /// Secret mixed into the `CmdAuth1` challenge.
+    fn secret1(&self, version: u8) -> Option<[u8; 8]>;
+    /// Secret mixed into the `CmdAuth2` challenge.
+    fn secret2(&self, version: u8) -> Option<[u8; 8]>;
+    /// AES-128 key of the challenge version.
+    fn key(&self, version: u8) -> Option<[u8; 16]>;
+    /// Key syscon encrypts the `CmdAuthGo` request with.
+    fn go_key1(&self) -> [u8; 16];
+    /// Key the `CmdAuthGo` response is decrypted with.
+    fn go_key2(&self) -> [u8; 16];
+    /// Second block of a valid `CmdAuthGo` request, once decrypted.
+    fn go_secret(&self) -> [u8; 16];
+}
+
+/// The key tables compiled into the library.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BuiltinKeys;
+
+impl KeyStore for BuiltinKeys {
+    fn secret1(&self, version: u8) -> Option<[u8; 8]> {
+        KEYRING.get(version).map(|entry| entry.secret1)
+    }
+
+    fn secret2(&self, version: u8) -> Option<[u8; 8]> {
+        KEYRING.get(version).map(|entry| entry.secret2)
+    }
+
+    fn key(&self, version: u8) -> Option<[u8; 16]> {
+        KEYRING.get(version).map(|entry| entry.key)
+    }
+
+    fn go_key1(&self) -> [u8; 16] {
+        GO_KEY1
+    }
+
+    fn go_key2(&self) -> [u8; 16] {
+        GO_KEY2
+    }
+
+    fn go_secret(&self) -> [u8; 16] {
+        GO_SECRET
+    }
+}
+
+impl<K: KeyStore + ?Sized> KeyStore for &K {
+    fn secret1(&self, version: u8) -> Option<[u8; 8]> {
+        (**self).secret1(version)
+    }
+
+    fn secret2(&self, version: u8) -> Option<[u8; 8]> {
+        (**self).secret2(version)
+    }
+
+    fn key(&self, version: u8) -> Option<[u8; 16]> {
+        (**self).key(version)
+    }
+
+    fn go_key1(&self) -> [u8; 16] {
+        (**self).go_key1()
+    }
+
+    fn go_key2(&self) -> [u8; 16] {
+        (**self).go_key2()
+    }
+
+    fn go_secret(&self) -> [u8; 16] {
+        (**self).go_secret()
+    }
+}
+
+/// Everything needed to answer one challenge version.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VersionKeys {
+    pub version: u8,
+    pub secret1: [u8; 8],
+    pub secret2: [u8; 8],
+    pub key: [u8; 16],
+}
+
+/// Fixed set of versions, checked when it is built.
+///
+/// `new` rejects duplicate versions and all-zero secrets or keys, the usual
+/// sign of a placeholder. Used in a `const`, a bad table fails to compile.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KeyRing<const N: usize> {
+    entries: [VersionKeys; N],
+}
+
+impl<const N: usize> KeyRing<N> {
+    pub const fn new(entries: [VersionKeys; N]) -> Self {
+        let mut i = 0;
+        while i < N {
+            let entry = &entries[i];
+            assert!(!all_zero(&entry.secret1), "KeyRing entry with an empty secret1");
+            assert!(!all_zero(&entry.secret2), "KeyRing entry with an empty secret2");
+            assert!(!all_zero(&entry.key), "KeyRing entry with an empty key");
+            let mut j = i + 1;
+            while j < N {
+                assert!(entries[j].version != entry.version, "duplicate version in KeyRing");
+                j += 1;
+            }
+            i += 1;
+        }
+        Self { entries }
+    }
+
+    pub const fn get(&self, version: u8) -> Option<&VersionKeys> {
+        let mut i = 0;
+        while i < N {
+            if self.entries[i].version == version {
+                return Some(&self.entries[i])
+            }
+            i += 1;
+        }
+        None
+    }
+
+    pub const fn contains(&self, version: u8) -> bool {
+        self.get(version).is_some()
+    }
+
+    pub const fn entries(&self) -> &[VersionKeys] {
+        &self.entries
+    }
+
+    pub fn versions(&self) -> impl Iterator<Item = u8> + '_ {
+        self.entries.iter().map(|entry| entry.version)
+    }
+}
+
+const fn all_zero(bytes: &[u8]) -> bool {
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != 0 {
+            return false
+        }
+        i += 1;
+    }
+    true
+}
+
+// the Go versions get special treatment in `CmdAuth2`
+const _: () = assert!(KEYRING.contains(0xeb) && KEYRING.contains(0xb3));
+
+/// Key store filled at runtime, e.g. from flash or a file, so a newly
+/// discovered challenge version does not need a new build.
+///
+/// The `CmdAuthGo` keys default to the built-in ones.
+#[derive(Clone, Debug)]
+pub struct KeyTable<const N: usize> {
+    entries: heapless::Vec<VersionKeys, N>,
+    go_key1: [u8; 16],
+    go_key2: [u8; 16],
+    go_secret: [u8; 16],
+}
+
+impl<const N: usize> KeyTable<N> {
+    pub fn new() -> Self {
+        Self {
+            entries: heapless::Vec::new(),
+            go_key1: GO_KEY1,
+            go_key2: GO_KEY2,
+            go_secret: GO_SECRET,
+        }
+    }
+
+    pub fn with_go_keys(mut self, go_key1: [u8; 16], go_key2: [u8; 16], go_secret: [u8; 16]) -> Self {
+        self.go_key1 = go_key1;
+        self.go_key2 = go_key2;
+        self.go_secret = go_secret;
+        self
+    }
+
+    /// Add the keys of a version, replacing any previous entry for it.
+    /// Gives the entry back if the table is full.
+    pub fn insert(&mut self, keys: VersionKeys) -> Result<(), VersionKeys> {
+        match self.entries.iter_mut().find(|entry| entry.version == keys.version) {
+            Some(entry) => {
+                *entry = keys;
+                Ok(())
+            },
+            None => self.entries.push(keys),
+        }
+    }
+
+    pub fn get(&self, version: u8) -> Option<&VersionKeys> {
+        self.entries.iter().find(|entry| entry.version == version)
+    }
+
+    pub fn versions(&self) -> impl Iterator<Item = u8> + '_ {
+        self.entries.iter().map(|entry| entry.version)
+    }
+}
+
+impl<const N: usize> Default for KeyTable<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> KeyStore for KeyTable<N> {
+    fn secret1(&self, version: u8) -> Option<[u8; 8]> {
+        self.get(version).map(|entry| entry.secret1)
+    }
+
+    fn secret2(&self, version: u8) -> Option<[u8; 8]> {
+        self.get(version).map(|entry| entry.secret2)
+    }
+
+    fn key(&self, version: u8) -> Option<[u8; 16]> {
+        self.get(version).map(|entry| entry.key)
+    }
+
+    fn go_key1(&self) -> [u8; 16] {
+        self.go_key1
+    }
+
+    fn go_key2(&self) -> [u8; 16] {
+        self.go_key2
+    }
+
+    fn go_secret(&self) -> [u8; 16] {
+        self.go_secret
+    }
+}
@@ -1,93 +1,208 @@
 #![cfg_attr(not(feature="std"), no_std)]
 
-use embedded_hal::{serial::{Read, Write}, timer::CountDown, digital::v2::OutputPin, blocking::delay::DelayMs};
+use embedded_hal::{serial::{Read, Write}, timer::{CountDown, Periodic}, digital::v2::OutputPin, blocking::delay::DelayMs};
 use nb::block;
 use num_enum::TryFromPrimitive;
-use aes::Aes128;
-use aes::cipher::{
-    KeyIvInit, BlockDecryptMut, BlockEncryptMut,
-    generic_array::GenericArray,
-};
 use ufmt::uWrite;
-use core::convert::{From, TryInto};
+use core::convert::From;
 use core::unreachable;
 
+#[cfg(feature="async")]
+pub mod asynch;
+mod auth;
+mod cipher;
+mod codec;
 mod consts;
+mod eeprom;
+mod keys;
+#[cfg(feature="eh1")]
+pub mod eh1;
+mod error;
+mod passthrough;
+mod profile;
+mod recorder;
+mod responder;
+mod sensors;
+mod simulation;
+mod syscon;
+mod ticker;
+mod timeout;
+
+use cipher::Ciphers;
+pub use auth::AuthState;
+pub use codec::{RawPayload, Request, Response};
+pub use eeprom::{Eeprom, EmulatedEeprom, EEPROM_SIZE};
+pub use error::Error;
+pub use keys::{BuiltinKeys, KeyRing, KeyStore, KeyTable, VersionKeys};
+pub use passthrough::{Passthrough, Relayed};
+pub use profile::{BatteryModel, BatteryProfile, MANUFACTURER_MAX_LEN, SERVICE_SERIALNO};
+pub use recorder::BatteryRecorder;
+pub use responder::{Frame, Frames, Responder};
+pub use sensors::{BatterySensors, NoSensors};
+pub use simulation::Simulation;
+pub use syscon::SysconEmulator;
+pub use ticker::NoTicker;
+pub use timeout::Timeout;
 
-use consts::*;
-
-#[cfg(any(feature="std", feature="usb"))]
 use log::{info, debug};
 //#[cfg(any(feature="rtt", not(feature="usb")))]
 //use defmt::{info, debug};
 
-#[cfg(feature="metro_m4")]
-type TimeoutType = fugit::NanosDurationU32;
-
-#[cfg(feature="rp2040")]
-type TimeoutType = fugit::MicrosDurationU64;
-
-#[cfg(feature="itsybitsy_m0")]
-type TimeoutType = itsybitsy_m0::hal::time::Nanoseconds;
-
-#[cfg(feature="test")]
-type TimeoutType = embedded_time::duration::Milliseconds;
-
+/// What to do with a received packet whose checksum does not match.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ChecksumPolicy {
+    /// Answer with a NAK, like a genuine battery does.
+    #[default]
+    Nak,
+    /// Drop the packet without answering.
+    Ignore,
+}
 
-pub struct BaryonSweeper<'a, S, C, P, T, D> 
+/// Blocking driver for embedded-hal 0.2.
+///
+/// `timer` times out each byte of a packet. The battery simulation only
+/// moves forward with a ticker, see `with_ticker`.
+pub struct BaryonSweeper<'a, S, C, P, T, D, E, K = BuiltinKeys, B = NoSensors, R = NoTicker> 
 where 
     S: Read<u8> + Write<u8>,
     C: CountDown,
     P: OutputPin,
-    T: From<TimeoutType> + Clone,
+    T: Timeout,
     D: DelayMs<u32>,
+    E: Eeprom,
+    K: KeyStore,
+    B: BatterySensors,
+    R: CountDown + Periodic,
 {
     serial: &'a mut S,
     timer: &'a mut C,
     led_pin: &'a mut P,
     timeout: T,
     delay: &'a mut D,
+    dispatcher: Dispatcher<E, K, B>,
+    ticker: Option<&'a mut R>,
+    ticking: bool,
 }
-    
-impl<'a, S, C, P, T, D> BaryonSweeper<'a, S, C, P, T, D>
+
+impl<'a, S, C, P, T, D, E, K, B> BaryonSweeper<'a, S, C, P, T, D, E, K, B>
 where 
     S: Read<u8> + Write<u8>,
     C: CountDown,
     P: OutputPin,
-    T: From<TimeoutType> + Clone,
+    T: Timeout,
     D: DelayMs<u32>,
+    E: Eeprom,
+    K: KeyStore,
+    B: BatterySensors,
 {
-    pub fn new(serial: &'a mut S, timer: &'a mut C, led_pin: &'a mut P, timeout: T, delay: &'a mut D) -> BaryonSweeper<'a, S, C, P, T, D> {
+    pub fn new(serial: &'a mut S, timer: &'a mut C, led_pin: &'a mut P, timeout: T, delay: &'a mut D, responder: Responder<E, K, B>) -> BaryonSweeper<'a, S, C, P, T, D, E, K, B> {
         Self {
             serial,
             timer,
             led_pin,
             timeout,
             delay,
+            dispatcher: Dispatcher::new(responder),
+            ticker: None,
+            ticking: false,
         }
     }
 
+    /// Move the battery simulation forward with `ticker`, a second timer
+    /// left running every `TICK_MS` for as long as the sweeper is, so the
+    /// time spent receiving and answering packets counts too.
+    pub fn with_ticker<R>(self, ticker: &'a mut R) -> BaryonSweeper<'a, S, C, P, T, D, E, K, B, R>
+    where
+        R: CountDown + Periodic,
+        <R as CountDown>::Time: Timeout,
+    {
+        BaryonSweeper {
+            serial: self.serial,
+            timer: self.timer,
+            led_pin: self.led_pin,
+            timeout: self.timeout,
+            delay: self.delay,
+            dispatcher: self.dispatcher,
+            ticker: Some(ticker),
+            ticking: false,
+        }
+    }
+}
+
+impl<'a, S, C, P, T, D, E, K, B, R> BaryonSweeper<'a, S, C, P, T, D, E, K, B, R>
+where 
+    S: Read<u8> + Write<u8>,
+    C: CountDown,
+    P: OutputPin,
+    T: Timeout,
+    D: DelayMs<u32>,
+    E: Eeprom,
+    K: KeyStore,
+    B: BatterySensors,
+    R: CountDown + Periodic,
+    <R as CountDown>::Time: Timeout,
+{
+
+    pub fn responder(&self) -> &Responder<E, K, B> {
+        &self.dispatcher.responder
+    }
+
+    pub fn responder_mut(&mut self) -> &mut Responder<E, K, B> {
+        &mut self.dispatcher.responder
+    }
+
+    pub fn set_checksum_policy(&mut self, policy: ChecksumPolicy) {
+        self.dispatcher.checksum_policy = policy;
+    }
+
+    /// Number of received packets dropped because of a bad checksum.
+    pub fn checksum_errors(&self) -> u32 {
+        self.dispatcher.checksum_errors
+    }
+
+
+    /// Move the battery simulation forward if the ticker expired.
+    fn tick(&mut self) {
+        let Some(ticker) = self.ticker.as_deref_mut() else {
+            return
+        };
+        if !self.ticking {
+            ticker.start(<R as CountDown>::Time::millis(TICK_MS));
+            self.ticking = true;
+        }
+        if ticker.wait().is_ok() {
+            self.dispatcher.responder.advance(TICK_MS);
+        }
+    }
 
-    fn read_with_timeout
+    /// Wait for the next byte. There is no limit on the wait for a header,
+    /// a byte inside a packet must come within `timeout`.
+    fn read_byte
         (
             &mut self,
-            timeout: T,
-        ) -> Result<u8, ()>
+            wait: FrameWait,
+        ) -> Result<u8, Error>
         where
-        T: core::convert::From<TimeoutType> ,<C as CountDown>::Time: From<T>
+        <C as CountDown>::Time: From<T>
     {
-        self.timer.start(timeout);
+        if wait == FrameWait::InterByte {
+            self.timer.start(self.timeout.clone());
+        }
 
         loop {
             match self.serial.read() {
                 // raise error
-                Err(nb::Error::Other(_e)) => return Err(()),//return Err(Error::Serial(e)),
+                Err(nb::Error::Other(_e)) => return Err(Error::Serial),
                 Err(nb::Error::WouldBlock) => {
                     // no data available yet, check the timer below
                 },
                 Ok(byte) => return Ok(byte),
             }
 
+            self.tick();
+            if wait == FrameWait::Header {
+                continue
+            }
             match self.timer.wait() {
                 Err(nb::Error::Other(_e)) => {
                     // The error type specified by `timer.wait()` is `!`, which
@@ -97,53 +212,17 @@ where
                 },
                 // no timeout yet, try again
                 Err(nb::Error::WouldBlock) => continue,
-                Ok(()) => return Err(()),//return Err(Error::TimedOut),
+                Ok(()) => return Err(Error::TimedOut),
             }
         }
     }
 
 
-    fn receive_packet(&mut self, recv: &mut [u8; 64], len: &mut u8)
+    fn receive_packet(&mut self, recv: &mut [u8; 64], len: &mut u8) -> Result<(), Error>
     where
-    T: core::convert::From<TimeoutType> ,<C as CountDown>::Time: From<T>
+    <C as CountDown>::Time: From<T>
     {
-        loop {
-            info!("Waiting for 5a");
-            cfg_if::cfg_if! {
-                if #[cfg(feature="std")] {
-                    use embedded_time::duration::Milliseconds;
-                    if let Ok(0x5a) = self.read_with_timeout(Milliseconds::new(5000).into())  {
-                        break;
-                    }
-                } else {
-                    if let Ok(0x5a) = self.read_with_timeout(TimeoutType::millis(5000).into())  {
-                        break;
-                    }
-                }
-            }
-        }
-        let length = block!(self.serial.read()).map_err(|_|()).unwrap();
-        *len = length-1;
-
-        for i in 0..length {
-            let res = self.read_with_timeout(self.timeout.clone());
-            if res.is_err() {
-                *len = 0;
-                return;
-            }
-            else {
-                recv[i as usize] = res.unwrap();
-            }
-        }
-       
-        
-        //#[cfg(debug_assertions)]
-        //{
-            let mut msg = heapless::String::<2048>::new();
-            let _ = ufmt::uwrite!(msg, "Received packet: 0x5A, 0x{:02X} ", length).unwrap();
-            let _ = msg.write_str(fmt_packet(recv, length.into()).as_str());
-            debug!("{}", msg.as_str());
-        //}
+        receive_frame(recv, len, |wait| self.read_byte(wait))
     }
 
 
@@ -156,230 +235,357 @@ where
             debug!("{}", msg.as_str());
         //}
         
-        for i in 0..size {
-            let _ = block!(self.serial.write(packet[i])).map_err(|_|());
+        for byte in &packet[..size] {
+            let _ = block!(self.serial.write(*byte)).map_err(|_|());
+        }
+    }
+
+    fn send_frames(&mut self, responses: &Frames) {
+        for frame in responses.iter() {
+            self.send_packet(frame, frame.len());
         }
     }
 
     pub fn sweep(&mut self) 
     where
-    T: core::convert::From<TimeoutType>, <C as CountDown>::Time: From<T>
+    <C as CountDown>::Time: From<T>
     {
-        let mut length: u8;
-        let mut challenge_version: u8 = 0;
-        let mut challenge1b = [0u8; 16];
-
-        length = 0;
-
         info!("Beginning the sweep!");
 
         loop {
-            self.sweep_iter(&mut length, &mut challenge_version, &mut challenge1b);
+            if let Err(e) = self.sweep_iter() {
+                info!("Sweep error: {}", e);
+            }
         }
     }
 
 
-    pub fn sweep_iter(&mut self, length: &mut u8, challenge_version: &mut u8, challenge1b: &mut [u8;16]) 
+    pub fn sweep_iter(&mut self) -> Result<(), Error>
     where
-    T: core::convert::From<TimeoutType>, <C as CountDown>::Time: From<T>
+    <C as CountDown>::Time: From<T>
     {
 
         let mut recv = [0u8;64];
+        let mut length = 0u8;
+
+        let received = self.receive_packet(&mut recv, &mut length);
+        let mut responses = Frames::new();
+        let result = match self.dispatcher.dispatch(received.map(|()| &recv[..length as usize]), &mut responses) {
+            Reply::Send(result) => result,
+            Reply::Drop(e) => return Err(e),
+        };
+
+        self.led_pin.set_low().map_err(|_| Error::Pin)?;
+        self.send_frames(&responses);
+        self.led_pin.set_high().map_err(|_| Error::Pin)?;
+        self.delay.delay_ms(1);
+        result
+    }
+}
 
-        self.receive_packet(&mut recv, length);
-        /*if length == 0 {
-            continue;
-        }*/
+/// The drivers count time in ticks of this length, each tick moves the
+/// battery simulation forward.
+const TICK_MS: u32 = 10;
 
-        self.led_pin.set_low().map_err(|_|()).unwrap();
+/// Which timeout applies to the next byte of a frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum FrameWait {
+    Header,
+    InterByte,
+}
 
-        match recv[0].try_into() {
-            Ok(Commands::CmdReadStatus) => {
-                let response = cmd_read_status();
-                let packet = build_packet(ResponseType::Ack as u8, &response);
-                self.send_packet(&packet.0, packet.1);
-            },
-            Ok(Commands::CmdReadTemperature) => {
-                let response = cmd_read_temperature();
-                let packet = build_packet(ResponseType::Ack as u8, &response);
-                self.send_packet(&packet.0, packet.1);
-            },
-            Ok(Commands::CmdReadVoltage) => {
-                let response = cmd_read_voltage();
-                let packet = build_packet(ResponseType::Ack as u8, &response);
-                self.send_packet(&packet.0, packet.1);
-            },
-            Ok(Commands::CmdReadCurrent) => {
-                let response = cmd_read_current();
-                let packet = build_packet(ResponseType::Ack as u8, &response);
-                self.send_packet(&packet.0, packet.1);
-            },
-            Ok(Commands::CmdReadCapacity) => {
-                let response = cmd_read_capacity();
-                let packet = build_packet(ResponseType::Ack as u8, &response);
-                self.send_packet(&packet.0, packet.1);
-            },
-            Ok(Commands::CmdRead8) => {
-                let response = cmd_read8();
-                let packet = build_packet(ResponseType::Ack as u8, &response);
-                self.send_packet(&packet.0, packet.1);
-            },
-            Ok(Commands::CmdReadTimeLeft) => {
-                let response = cmd_read_time_left();
-                let packet = build_packet(ResponseType::Ack as u8, &response);
-                self.send_packet(&packet.0, packet.1);
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum FrameState {
+    Header,
+    Length,
+    Body,
+}
 
-            },
-            Ok(Commands::CmdRead11) => {
-                let response = cmd_read11();
-                let packet = build_packet(ResponseType::Ack as u8, &response);
-                self.send_packet(&packet.0, packet.1);
-            },
-            Ok(Commands::CmdReadSerialno) => {
-                let response = cmd_read_serialno();
-                let packet = build_packet(ResponseType::Ack as u8, &response);
-                self.send_packet(&packet.0, packet.1);
-            },
-            Ok(Commands::CmdRead13) => {
-                let response = cmd_read13();
-                let packet = build_packet(ResponseType::Ack as u8, &response);
-                self.send_packet(&packet.0, packet.1);
-            },
-            Ok(Commands::CmdRead22) => {
-                let response = cmd_read22();
-                let packet = build_packet(ResponseType::Ack as u8, &response);
-                self.send_packet(&packet.0, packet.1);
-            },
-            Ok(Commands::CmdAuth1) => {
-                *challenge_version = recv[1];
-                let challenge = &recv[2..];
-                if let Ok((response, bchal)) = cmdauth1(*challenge_version, challenge)
-                {
-                    info!("Challenge version: 0x{:x}", *challenge_version);
-                    *challenge1b = bchal;
-                    let packet = build_packet(ResponseType::Ack as u8, &response);
-                    self.send_packet(&packet.0, packet.1);
-                }
-                else {
-                    info!("Challenge version: 0x{:x}", *challenge_version);
-                    let response = [0xff; 8];
-                    let packet = build_packet(ResponseType::Ack as u8, &response);
-                    self.send_packet(&packet.0, packet.1); 
+/// Byte at a time 0x5A packet parser, shared by the blocking and async
+/// drivers so they resync and reject packets the same way.
+struct FrameReader {
+    /// Header bytes a packet may start with.
+    headers: &'static [u8],
+    header: u8,
+    recv: [u8; 64],
+    length: u8,
+    pos: u8,
+    state: FrameState,
+}
+
+impl FrameReader {
+    fn new() -> Self {
+        Self::with_headers(&[0x5a])
+    }
+
+    /// Parse packets starting with any of `headers`, e.g. the 0xA5
+    /// responses of a real battery.
+    fn with_headers(headers: &'static [u8]) -> Self {
+        Self {
+            headers,
+            header: 0,
+            recv: [0u8; 64],
+            length: 0,
+            pos: 0,
+            state: FrameState::Header,
+        }
+    }
+
+    fn wait(&self) -> FrameWait {
+        match self.state {
+            FrameState::Header => FrameWait::Header,
+            _ => FrameWait::InterByte,
+        }
+    }
+
+    /// Feed the next byte. Returns `Some` once a whole packet is in.
+    fn feed(&mut self, byte: u8) -> Option<Result<(), Error>> {
+        match self.state {
+            FrameState::Header => {
+                if self.headers.contains(&byte) {
+                    self.header = byte;
+                    self.state = FrameState::Length;
                 }
+                None
             },
-            Ok(Commands::CmdAuth2) => {
-                let challenge = &recv[2..];
-                if let Ok(response) = cmdauth2(*challenge_version, challenge, challenge1b)
-                {
-                    info!("Challenge version: 0x{:x}", *challenge_version);
-                    let packet = build_packet(ResponseType::Ack as u8, &response);
-                    self.send_packet(&packet.0, packet.1);
-                }
-                if *challenge_version == 0xeb || *challenge_version == 0xb3 {
-                    let packet2 = [0x5a, 0x02, 0x01, 0xa2];
-                    self.send_packet(&packet2, packet2.len());
+            FrameState::Length => {
+                // the length covers the command byte and the checksum, the
+                // whole packet has to fit in a `Frame`
+                if (2..=self.recv.len() - 2).contains(&(byte as usize)) {
+                    self.length = byte;
+                    self.pos = 0;
+                    self.state = FrameState::Body;
+                } else {
+                    info!("Dropping packet with bad length 0x{:02x}", byte);
+                    // a bad length byte can be the header of the next packet
+                    if self.headers.contains(&byte) {
+                        self.header = byte;
+                        self.state = FrameState::Length;
+                    } else {
+                        self.state = FrameState::Header;
+                    }
                 }
+                None
             },
-            Ok(Commands::CmdAuthGo) => {
-                let screq = &recv[1..];
-                if let Ok(response) = cmdauthgo(screq)
-                {
-                    let packet = build_packet(ResponseType::Ack as u8, &response);
-                    self.send_packet(&packet.0, packet.1);
+            FrameState::Body => {
+                self.recv[self.pos as usize] = byte;
+                self.pos += 1;
+                if self.pos < self.length {
+                    return None
                 }
-                else 
-                {
-                    info!("CmdAuthGo returned error")
+                self.state = FrameState::Header;
+
+                //#[cfg(debug_assertions)]
+                //{
+                    let mut msg = heapless::String::<2048>::new();
+                    let _ = ufmt::uwrite!(msg, "Received packet: 0x{:02X}, 0x{:02X} ", self.header, self.length);
+                    let _ = msg.write_str(fmt_packet(&self.recv, self.length.into()).as_str());
+                    debug!("{}", msg.as_str());
+                //}
+
+                if !verify_checksum(self.header, self.length, &self.recv[..self.length as usize]) {
+                    info!("Received packet with bad checksum");
+                    return Some(Err(Error::Checksum))
                 }
+                Some(Ok(()))
+            },
+        }
+    }
+
+    /// The next byte did not arrive, give up on the current packet.
+    fn truncated(&mut self) {
+        match self.state {
+            FrameState::Length => info!("Packet truncated before its length byte"),
+            _ => info!("Packet truncated after {} of {} bytes", self.pos, self.length),
+        }
+        self.state = FrameState::Header;
+        self.length = 0;
+    }
+
+    /// Take the outcome of waiting for the next byte, as `receive_frame`
+    /// does. Returns `Some` once the packet is in or given up on; a missing
+    /// byte only ends the packet once its header has arrived.
+    fn step(&mut self, wait: FrameWait, read: Result<u8, Error>) -> Option<Result<(), Error>> {
+        match read {
+            Ok(byte) => self.feed(byte),
+            Err(_) if wait == FrameWait::Header => None,
+            Err(e) => {
+                self.truncated();
+                Some(Err(e))
             },
-            _ => {
-                let packet = build_packet(ResponseType::Nak as u8, &[]);
-                self.send_packet(&packet.0, packet.1);
-                    info!("Sending General NAK!");
-            }           
         }
+    }
 
-        self.led_pin.set_high().map_err(|_|()).unwrap();
-        self.delay.delay_ms(1);
+    /// Copy the last packet out. `len` is the number of bytes before the
+    /// checksum, 0 if the packet was truncated.
+    fn finish(&self, recv: &mut [u8; 64], len: &mut u8) {
+        *recv = self.recv;
+        *len = self.length.saturating_sub(1);
     }
+
+    /// The last packet as it was on the wire.
+    fn frame(&self) -> Frame {
+        let mut bytes = [0u8; 64];
+        let len = self.length as usize + 2;
+        bytes[0] = self.header;
+        bytes[1] = self.length;
+        bytes[2..len].copy_from_slice(&self.recv[..len - 2]);
+        Frame::new(bytes, len)
+    }
+}
+
+/// Read one 0x5A packet through `read_byte`.
+///
+/// On success `recv` holds the command, its arguments and the checksum,
+/// and `len` the number of bytes before the checksum.
+fn receive_frame<F>(recv: &mut [u8; 64], len: &mut u8, mut read_byte: F) -> Result<(), Error>
+where
+    F: FnMut(FrameWait) -> Result<u8, Error>,
+{
+    let mut reader = FrameReader::new();
+    info!("Waiting for 5a");
+    loop {
+        let wait = reader.wait();
+        if let Some(result) = reader.step(wait, read_byte(wait)) {
+            reader.finish(recv, len);
+            return result
+        }
+    }
+}
+
+/// What a driver does once a packet has been received.
+enum Reply {
+    /// Send the responses, blinking the LED, then report the result.
+    Send(Result<(), Error>),
+    /// Send nothing.
+    Drop(Error),
+}
+
+/// Everything past receiving a packet that does not touch the hardware:
+/// checksum accounting, the `ChecksumPolicy` and the `Responder`. Shared by
+/// the drivers so they answer alike.
+struct Dispatcher<E: Eeprom, K: KeyStore, B: BatterySensors> {
+    responder: Responder<E, K, B>,
+    checksum_policy: ChecksumPolicy,
+    checksum_errors: u32,
 }
 
-fn cmd_read_status() -> [u8;3] {
+impl<E: Eeprom, K: KeyStore, B: BatterySensors> Dispatcher<E, K, B> {
+    fn new(responder: Responder<E, K, B>) -> Self {
+        Self {
+            responder,
+            checksum_policy: ChecksumPolicy::default(),
+            checksum_errors: 0,
+        }
+    }
+
+    /// Fill `responses` for `received`, the request or the reason there is
+    /// none, as returned by `receive_frame`.
+    fn dispatch(&mut self, received: Result<&[u8], Error>, responses: &mut Frames) -> Reply {
+        responses.clear();
+        match received {
+            Ok(request) => Reply::Send(self.responder.respond(request, responses)),
+            Err(Error::Checksum) => {
+                self.checksum_errors = self.checksum_errors.wrapping_add(1);
+                match self.checksum_policy {
+                    ChecksumPolicy::Nak => {
+                        responses.push(Frame::nak());
+                        Reply::Send(Err(Error::Checksum))
+                    },
+                    ChecksumPolicy::Ignore => Reply::Drop(Error::Checksum),
+                }
+            },
+            Err(e) => Reply::Drop(e),
+        }
+    }
+}
+
+fn cmd_read_status(profile: &BatteryProfile) -> [u8;3] {
     info!("CmdReadStatus");
-    [0x10, 0xc3, 0x06]
+    profile.status
 }
 
-fn cmd_read_temperature() -> [u8;1] {
+fn cmd_read_temperature(profile: &BatteryProfile) -> [u8;1] {
     info!("CmdReadTemperature");
-    [27]
+    [profile.temperature]
 }
 
-fn cmd_read_voltage() -> [u8;2] {
+fn cmd_read_voltage(profile: &BatteryProfile) -> [u8;2] {
     info!("CmdReadVoltage");
-    [0x36, 0x10]
+    profile.voltage.to_le_bytes()
 }
 
-fn cmd_read_current() -> [u8;2] {
+fn cmd_read_current(profile: &BatteryProfile) -> [u8;2] {
     info!("CmdReadCurrent");
-    let current: u16 = 4200;
-    current.to_le_bytes()
+    profile.current.to_le_bytes()
 }
 
-fn cmd_read_capacity() -> [u8;2] {
+fn cmd_read_capacity(profile: &BatteryProfile) -> [u8;2] {
     info!("CmdReadCapacity");
-    let capacity: u16 = 1800;
-    capacity.to_le_bytes()
+    profile.capacity.to_le_bytes()
 }
 
-fn cmd_read8() -> [u8;2] {
+fn cmd_read8(profile: &BatteryProfile) -> [u8;2] {
     info!("CmdRead8");
-    let read8: u16 = 1250; 
-    read8.to_le_bytes()
+    profile.read8.to_le_bytes()
 }
 
-fn cmd_read_time_left() -> [u8;2] {
+fn cmd_read_time_left(profile: &BatteryProfile) -> [u8;2] {
     info!("CmdReadTimeLeft");
-    let time_left: u16 = 1025;
-    time_left.to_le_bytes()
+    profile.time_left.to_le_bytes()
 }
 
-fn cmd_read11() -> [u8;2] {
+fn cmd_read11(profile: &BatteryProfile) -> [u8;2] {
     info!("CmdRead11");
-    let read11: u16 = 15;
-    read11.to_le_bytes()
+    profile.read11.to_le_bytes()
 }
 
-fn cmd_read_serialno() -> [u8; 4] {
+fn cmd_read_serialno(profile: &BatteryProfile) -> [u8; 4] {
     info!("CmdReadSerialno");
-    [SERIALNO[1], SERIALNO[0], SERIALNO[3], SERIALNO[2]]
+    let serialno = profile.serial_number;
+    [serialno[1], serialno[0], serialno[3], serialno[2]]
 }
 
-fn cmd_read13() -> [u8; 5] {
+fn cmd_read13(profile: &BatteryProfile) -> [u8; 5] {
     info!("CmdRead13");
-    [0x9d, 0x10, 0x10, 0x28, 0x14]
+    profile.read13
 }
 
-fn cmd_read22() -> [u8; 17]
+fn cmd_read22(profile: &BatteryProfile) -> &[u8]
 {
     info!("CmdRead22");
-    *b"SonyEnergyDevices"
+    profile.manufacturer()
+}
+
+fn cmd_write_eeprom<E: Eeprom>(eeprom: &mut E, args: &[u8]) -> Result<(), Error> {
+    info!("CmdWriteEeprom");
+    match args {
+        [address, value] => eeprom.write(*address, *value).ok_or(Error::EepromAddress(*address)),
+        _ => Err(Error::BadLength),
+    }
 }
 
-fn cmdauth1(version: u8, challenge: &[u8]) -> Result<([u8; 16], [u8; 16]), ()> {
+fn cmd_read_eeprom<E: Eeprom>(eeprom: &E, args: &[u8]) -> Result<[u8; 1], Error> {
+    info!("CmdReadEeprom");
+    match args {
+        [address] => eeprom.read(*address).map(|value| [value]).ok_or(Error::EepromAddress(*address)),
+        _ => Err(Error::BadLength),
+    }
+}
+
+fn cmdauth1<K: KeyStore>(ciphers: &mut Ciphers<K>, version: u8, challenge: &[u8]) -> Result<([u8; 16], [u8; 16]), Error> {
     info!("CmdAuth1");
     let mut challenge1a = [0u8; 16];
     let mut challenge1b = [0u8; 16];
     let mut data = [0u8; 16];
 
-    if mix_challenge1(version, challenge, &mut data).is_err() {
-       return Err(())
-    }
+    mix_challenge1(ciphers.keys(), version, challenge, &mut data)?;
 
-    encrypt_bytes(&data, version, &mut challenge1a).unwrap();
+    encrypt_bytes(ciphers, &data, version, &mut challenge1a)?;
 
     let second = challenge1a;
     let mut temp = [0u8; 16];
-    encrypt_bytes(&second, version, &mut temp).unwrap();
+    encrypt_bytes(ciphers, &second, version, &mut temp)?;
 
     matrix_swap(&temp, &mut challenge1b);
 
@@ -390,145 +596,111 @@ fn cmdauth1(version: u8, challenge: &[u8]) -> Result<([u8; 16], [u8; 16]), ()> {
     Ok((packet, challenge1b))
 }
 
-fn cmdauth2(challenge_version: u8, _challenge: &[u8], ch1b: &[u8]) -> Result<[u8; 16], ()>
+fn cmdauth2<K: KeyStore>(ciphers: &mut Ciphers<K>, challenge_version: u8, _challenge: &[u8], ch1b: &[u8]) -> Result<[u8; 16], Error>
 {
     info!("CmdAuth2");
+    let mut packet = [0u8; 16];
+
+    let challenge2 = challenge2(ciphers, challenge_version, ch1b)?;
+
+    encrypt_bytes(ciphers, &challenge2, challenge_version, &mut packet)?;
+
+    Ok(packet)
+}
+
+/// Derive the `CmdAuth2` block from the battery's `challenge1b`. Syscon
+/// sends the first half of it as the `CmdAuth2` argument.
+fn challenge2<K: KeyStore>(ciphers: &mut Ciphers<K>, challenge_version: u8, ch1b: &[u8]) -> Result<[u8; 16], Error>
+{
     let mut data2 = [0u8; 16];
     let mut challenge2 = [0u8; 16];
     let mut temp = [0u8; 16];
-    let mut packet = [0u8; 16];
 
-    mix_challenge2(challenge_version, &ch1b[0..8], &mut temp).unwrap();
+    mix_challenge2(ciphers.keys(), challenge_version, ch1b.get(0..8).ok_or(Error::BadLength)?, &mut temp)?;
 
     matrix_swap(&temp, &mut data2);
 
+    encrypt_bytes(ciphers, &data2, challenge_version, &mut challenge2)?;
 
-    encrypt_bytes(&data2, challenge_version, &mut challenge2).unwrap();
-
-    encrypt_bytes(&challenge2, challenge_version, &mut packet).unwrap();
-
-    Ok(packet)
+    Ok(challenge2)
 }
 
-fn cmdauthgo(screq: &[u8]) -> Result<[u8; 40], ()>
+fn cmdauthgo<K: KeyStore>(ciphers: &mut Ciphers<K>, screq: &[u8]) -> Result<[u8; 40], Error>
 {
     info!("CmdAuthGo");
+    if screq.len() < 40 {
+        return Err(Error::BadLength)
+    }
     let mut enc = [[0u8; 16]; 2];
     enc[0].copy_from_slice(&screq[8..24]);
     enc[1].copy_from_slice(&screq[24..40]);
-    let key = GenericArray::from(GO_KEY1);
-    let iv = GenericArray::from([0u8; 16]);
-
-    let mut decryptor = cbc::Decryptor::<Aes128>::new(&key, &iv);
-    let block1 = GenericArray::from(enc[0]);
-    let block2 = GenericArray::from(enc[1]);
-    let mut blocks = [block1, block2];
-    decryptor.decrypt_blocks_mut(&mut blocks);
-
+    let decrypted = ciphers.go_decrypt_request(enc);
 
-    let decrypted = blocks.as_slice();
-
-    if decrypted[1].as_slice() == GO_SECRET {
+    if decrypted[1] == ciphers.keys().go_secret() {
         info!("Go handshake request is valid");
     } else {
         info!("Invalid request from Syscon");
         let mut msg = heapless::String::<2048>::new();
         let _ = msg.write_str(fmt_packet(decrypted[1].as_slice(), decrypted[1].as_slice().len()).as_str());
         debug!("{}", msg.as_str());
-        return Err(())
+        return Err(Error::GoValidation)
     }
 
     let mut response_payload = [[0u8; 16]; 2];
     response_payload[0][0..8].copy_from_slice(&decrypted[0][8..16]);
     response_payload[0][8..16].copy_from_slice(&decrypted[0][0..8]);
 
-    let key = GenericArray::from(GO_KEY2);
-    let mut decryptor = cbc::Decryptor::<Aes128>::new(&key, &iv);
-    let block1 = GenericArray::from(response_payload[0]);
-    let block2 = GenericArray::from(response_payload[1]);
-    let mut blocks = [block1, block2];
-    decryptor.decrypt_blocks_mut(&mut blocks);
-    let decrypted = blocks.as_slice();
+    let decrypted = ciphers.go_decrypt_response(response_payload);
 
     let mut packet = [0u8; 40];
     packet[0..8].copy_from_slice(&[0x20, 0x01, 0x00, 0x00, 0x82, 0x82, 0x82, 0x82]);
-    packet[8..24].copy_from_slice(decrypted[0].as_slice());
-    packet[24..40].copy_from_slice(decrypted[1].as_slice());
+    packet[8..24].copy_from_slice(&decrypted[0]);
+    packet[24..40].copy_from_slice(&decrypted[1]);
     Ok(packet)
 }
 
-fn mix_challenge1(version: u8, challenge: &[u8], data: &mut [u8]) -> Result<(), ()>
+fn mix_challenge1<K: KeyStore>(keys: &K, version: u8, challenge: &[u8], data: &mut [u8]) -> Result<(), Error>
 {
-    let mut secret1: Option<[u8;8]> = None;
-    for i in 0..SECRETS1.len() {
-        if SECRETS1[i].version == version {
-            secret1 = Some(SECRETS1[i].secret);
-        }
-    }
-    if secret1.is_none() {
+    let Some(secret1) = keys.secret1(version) else {
         info!("secret1 not found");
-        Err(())
-    } else {
-        for i in 0..8 {
-            data[i] = secret1.unwrap()[i];
-        }
-        for i in 0..8 {
-            data[8+i] = challenge[i];
-        }
-        Ok(())
-    }
+        return Err(Error::UnknownChallengeVersion(version))
+    };
+    let challenge = challenge.get(0..8).ok_or(Error::BadLength)?;
+    data[0..8].copy_from_slice(&secret1);
+    data[8..16].copy_from_slice(challenge);
+    Ok(())
 }
 
-fn mix_challenge2(version: u8, challenge: &[u8], data: &mut [u8]) -> Result<(), ()>
+fn mix_challenge2<K: KeyStore>(keys: &K, version: u8, challenge: &[u8], data: &mut [u8]) -> Result<(), Error>
 {
-    let mut secret2: Option<[u8;8]> = None;
-    for i in 0..SECRETS2.len() {
-        if SECRETS2[i].version == version {
-            secret2 = Some(SECRETS2[i].secret);
-        }
-    }
-    if secret2.is_none() {
+    let Some(secret2) = keys.secret2(version) else {
         info!("secret2 not found");
-        Err(())
-    } else {
-        let secret2 = secret2.unwrap();
-        data[0x00] = challenge[0x00];
-        data[0x04] = challenge[0x01];
-        data[0x08] = challenge[0x02];
-        data[0x0C] = challenge[0x03];
-        data[0x01] = challenge[0x04];
-        data[0x05] = challenge[0x05];
-        data[0x09] = challenge[0x06];
-        data[0x0D] = challenge[0x07];
-        data[0x02] = secret2[0x00];
-        data[0x06] = secret2[0x01];
-        data[0x0A] = secret2[0x02];
-        data[0x0E] = secret2[0x03];
-        data[0x03] = secret2[0x04];
-        data[0x07] = secret2[0x05];
-        data[0x0B] = secret2[0x06];
-        data[0x0F] = secret2[0x07];
-        Ok(())
-    }
+        return Err(Error::UnknownChallengeVersion(version))
+    };
+    let challenge = challenge.get(0..8).ok_or(Error::BadLength)?;
+    data[0x00] = challenge[0x00];
+    data[0x04] = challenge[0x01];
+    data[0x08] = challenge[0x02];
+    data[0x0C] = challenge[0x03];
+    data[0x01] = challenge[0x04];
+    data[0x05] = challenge[0x05];
+    data[0x09] = challenge[0x06];
+    data[0x0D] = challenge[0x07];
+    data[0x02] = secret2[0x00];
+    data[0x06] = secret2[0x01];
+    data[0x0A] = secret2[0x02];
+    data[0x0E] = secret2[0x03];
+    data[0x03] = secret2[0x04];
+    data[0x07] = secret2[0x05];
+    data[0x0B] = secret2[0x06];
+    data[0x0F] = secret2[0x07];
+    Ok(())
 }
 
-fn encrypt_bytes(plain_bytes: &[u8; 16], version: u8, encrypted: &mut [u8]) -> Result<(), ()>
+fn encrypt_bytes<K: KeyStore>(ciphers: &mut Ciphers<K>, plain_bytes: &[u8; 16], version: u8, encrypted: &mut [u8]) -> Result<(), Error>
 {
-    let mut key: Option<[u8;16]> = None;
-    for i in 0..KEYS.len() {
-        if KEYS[i].version == version {
-            key = Some(KEYS[i].key);
-        }
-    }
-    if key.is_none() {
-        Err(())
-    } else {
-        let mut ctx = cbc::Encryptor::<Aes128>::new(&GenericArray::from(key.unwrap()), &GenericArray::from([0u8; 16]));
-        let mut block = GenericArray::from(*plain_bytes);
-        ctx.encrypt_block_mut(&mut block);
-        encrypted.copy_from_slice(block.as_slice());
-        Ok(())
-    }
+    encrypted.copy_from_slice(&ciphers.encrypt(version, plain_bytes)?);
+    Ok(())
 }
 
 const NEW_MAP: [usize; 16] = [
@@ -546,21 +718,44 @@ fn checksum(packet: &[u8]) -> u8 {
     (0xFFu16 - (sh & 0xffu16)) as u8
 }
 
-fn build_packet(code: u8, packet: &[u8]) -> ([u8;64], usize) {
+/// Check the trailing checksum of a received packet. `body` is everything
+/// after the length byte, checksum included.
+fn verify_checksum(header: u8, length: u8, body: &[u8]) -> bool {
+    let Some((received, body)) = body.split_last() else {
+        return false
+    };
+    let sum = body.iter().fold(header.wrapping_add(length), |sum, byte| sum.wrapping_add(*byte));
+    0xFF - sum == *received
+}
+
+/// Build a 0x5A request packet, as sent by syscon.
+fn build_request(command: u8, args: &[u8]) -> Result<([u8;64], usize), Error> {
+    let (mut full_packet, len) = build_packet(command, args)?;
+    full_packet[0] = 0x5A;
+    full_packet[len - 1] = checksum(&full_packet[0..len - 1]);
+    Ok((full_packet, len))
+}
+
+/// Largest payload that fits in a response packet.
+const MAX_PAYLOAD: usize = 60;
+
+fn build_packet(code: u8, packet: &[u8]) -> Result<([u8;64], usize), Error> {
+    if packet.len() > MAX_PAYLOAD {
+        return Err(Error::BadLength)
+    }
     let mut full_packet = [0u8; 64];
     full_packet[0] = 0xA5;
     full_packet[1] = (packet.len() + 2) as u8;
     full_packet[2] = code;
     full_packet[3..packet.len()+3].copy_from_slice(packet);
     full_packet[packet.len() + 3] = checksum(&full_packet[0..packet.len()+3]);
-    (full_packet, packet.len()+4)
+    Ok((full_packet, packet.len()+4))
 }
 
 fn fmt_packet(packet: &[u8], size: usize) -> heapless::String<2048> {
     let mut msg = heapless::String::<2048>::new();
     let _ = ufmt::uwrite!(msg, "[");
-    for i in 0..size {
-        let byte = packet[i];
+    for (i, &byte) in packet[..size].iter().enumerate() {
         if i == size-1 {
             let _ = ufmt::uwrite!(msg, "0x{:02X}", byte);
         }
@@ -595,6 +790,7 @@ enum Commands {
 }
 
 #[repr(u8)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, TryFromPrimitive)]
 enum ResponseType {
     Nak = 5,
     Ack,
@@ -603,6 +799,7 @@ enum ResponseType {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use embedded_hal_mock::eh0 as eh0_mock;
 
     #[test]
     fn test_challenge_response_cmdauth1() {
@@ -617,9 +814,9 @@ mod tests {
 
         let challenge_version = challenge[3];
         let ch = &challenge[4..];
-        if let Ok((packet, ch1b)) = cmdauth1(challenge_version, ch) {
+        if let Ok((packet, ch1b)) = cmdauth1(&mut Ciphers::new(BuiltinKeys), challenge_version, ch) {
             info!("{:x?}", ch1b);
-            let send = build_packet(code, &packet);
+            let send = build_packet(code, &packet).unwrap();
             assert_eq!(send.0[19], expected_response[19]);
 
             let mut msg = heapless::String::<2048>::new();
@@ -629,7 +826,7 @@ mod tests {
             assert_eq!(expected_response, send.0[..send.1]);
         } else {
             let packet = [0xff; 8];
-            let send = build_packet(code, &packet);
+            let send = build_packet(code, &packet).unwrap();
             assert_eq!(expected_response, send.0[..send.1]);
         }
     }
@@ -649,8 +846,8 @@ mod tests {
 
         let challenge1b = [0x1A, 0xC9, 0x21, 0x7A, 0xE9, 0x8F, 0xBE, 0x22, 0x54, 0x0a, 0x8c, 0xbb, 0xc1, 0xac, 0xf7, 0xfa];
 
-        if let Ok(packet) = cmdauth2(challenge_version, &challenge, &challenge1b) {
-            let send = build_packet(code, &packet);
+        if let Ok(packet) = cmdauth2(&mut Ciphers::new(BuiltinKeys), challenge_version, &challenge, &challenge1b) {
+            let send = build_packet(code, &packet).unwrap();
             assert_eq!(send.0[19], expected_response[19]);
 
             let mut msg = heapless::String::<2048>::new();
@@ -676,9 +873,9 @@ mod tests {
 
         let challenge_version = challenge[3];
         let ch = &challenge[4..];
-        if let Ok((packet, ch1b)) = cmdauth1(challenge_version, ch) {
+        if let Ok((packet, ch1b)) = cmdauth1(&mut Ciphers::new(BuiltinKeys), challenge_version, ch) {
             debug!("ch1b: {:x?}", ch1b);
-            let send = build_packet(code, &packet);
+            let send = build_packet(code, &packet).unwrap();
             assert_eq!(send.0[19], expected_response[19]);
 
             let mut msg = heapless::String::<2048>::new();
@@ -688,7 +885,7 @@ mod tests {
             assert_eq!(expected_response, send.0[..send.1]);
         } else {
             let packet = [0xffu8; 8];
-            let send = build_packet(code, &packet);
+            let send = build_packet(code, &packet).unwrap();
             assert_eq!(expected_response, send.0[..send.1]);
         }
     }
@@ -709,8 +906,8 @@ mod tests {
 
         let challenge1b = [0x0d, 0xf8, 0xf8, 0x84, 0x95, 0x45, 0x84, 0x3a,
                            0x4d, 0x84, 0x7f, 0x54, 0x7a, 0xd6, 0x2d, 0x77];
-        if let Ok(packet) = cmdauth2(challenge_version, &challenge, &challenge1b) {
-            let send = build_packet(code, &packet);
+        if let Ok(packet) = cmdauth2(&mut Ciphers::new(BuiltinKeys), challenge_version, &challenge, &challenge1b) {
+            let send = build_packet(code, &packet).unwrap();
             assert_eq!(send.0[19], expected_response[19]);
 
             let mut msg = heapless::String::<2048>::new();
@@ -733,9 +930,9 @@ mod tests {
 
         let challenge_version = challenge[3];
         let ch = &challenge[4..];
-        if let Ok((packet, ch1b)) = cmdauth1(challenge_version, ch) {
+        if let Ok((packet, ch1b)) = cmdauth1(&mut Ciphers::new(BuiltinKeys), challenge_version, ch) {
             debug!("ch1b: {:x?}", ch1b);
-            let send = build_packet(code, &packet);
+            let send = build_packet(code, &packet).unwrap();
             assert_eq!(send.0[19], expected_response[19]);
 
             let mut msg = heapless::String::<2048>::new();
@@ -745,7 +942,7 @@ mod tests {
             assert_eq!(expected_response, send.0[..send.1]);
         } else {
             let packet = [0xffu8; 8];
-            let send = build_packet(code, &packet);
+            let send = build_packet(code, &packet).unwrap();
             assert_eq!(expected_response, send.0[..send.1]);
         }
     }
@@ -761,8 +958,8 @@ mod tests {
         let code: u8 = ResponseType::Ack as u8;
         let challenge_version = 0xEB;
 
-        if let Ok(packet) = cmdauth2(challenge_version, &challenge, &ch1b) {
-            let send = build_packet(code, &packet);
+        if let Ok(packet) = cmdauth2(&mut Ciphers::new(BuiltinKeys), challenge_version, &challenge, &ch1b) {
+            let send = build_packet(code, &packet).unwrap();
             assert_eq!(send.0[19], expected_response[19]);
 
             let mut msg = heapless::String::<2048>::new();
@@ -786,8 +983,8 @@ mod tests {
         let code = ResponseType::Ack as u8;
         let screq = &challenge[3..];
     
-        if let Ok(packet) = cmdauthgo(&screq) {
-            let send = build_packet(code, &packet);
+        if let Ok(packet) = cmdauthgo(&mut Ciphers::new(BuiltinKeys), &screq) {
+            let send = build_packet(code, &packet).unwrap();
             let mut msg = heapless::String::<2048>::new();
             let _ = ufmt::uwrite!(msg, "Sending packet: ");
             let _ = msg.write_str(fmt_packet(&send.0, send.1).as_str());
@@ -810,6 +1007,7 @@ mod tests {
         let mut led = digital::Mock::new(&expectations);
         let timeout = Milliseconds::new(500);
         let mut delay = delay::NoopDelay::new();
+        let mut eeprom = EmulatedEeprom::default();
 
 
         let _ = embedded_logger::StdLogger::init();
@@ -821,14 +1019,14 @@ mod tests {
         let ts = serial::Transaction::read_many(packet);
         let mut ser = serial::Mock::new(&[ts]);
 
-        let mut bs = BaryonSweeper::new(&mut ser, &mut timer, &mut led, timeout, &mut delay);
+        let mut bs = BaryonSweeper::new(&mut ser, &mut timer, &mut led, timeout, &mut delay, Responder::new(&mut eeprom, BatteryProfile::default()));
         let mut recv_buffer = [0u8; 64];
         let mut length = 0;
-        bs.receive_packet(&mut recv_buffer, &mut length);
+        bs.receive_packet(&mut recv_buffer, &mut length).unwrap();
         assert_eq!(length, 41);
-        let response = cmdauthgo(&recv_buffer[1..]).unwrap();
+        let response = cmdauthgo(&mut Ciphers::new(BuiltinKeys), &recv_buffer[1..]).unwrap();
         let code = ResponseType::Ack as u8;
-        let send = build_packet(code, &response);
+        let send = build_packet(code, &response).unwrap();
         assert_eq!(expected_response, send.0[..send.1]);
         ser.done();
         led.done();
@@ -870,6 +1068,7 @@ mod tests {
         let mut led = digital::Mock::new(&led_expectations);
         let timeout = Milliseconds::new(500);
         let mut delay = delay::NoopDelay::new();
+        let mut eeprom = EmulatedEeprom::default();
 
 
         let _ = embedded_logger::StdLogger::init();
@@ -949,12 +1148,9 @@ mod tests {
         ];
         let mut ser = serial::Mock::new(&transactions);
 
-        let mut bs = BaryonSweeper::new(&mut ser, &mut timer, &mut led, timeout, &mut delay);
-        let mut length = 0;
-        let mut challenge_version = 0;
-        let mut challenge1b = [0u8; 16];
+        let mut bs = BaryonSweeper::new(&mut ser, &mut timer, &mut led, timeout, &mut delay, Responder::new(&mut eeprom, BatteryProfile::default()));
         for _ in 0..12 {
-            bs.sweep_iter(&mut length, &mut  challenge_version, &mut challenge1b);
+            bs.sweep_iter().unwrap();
         }
         ser.done();
         led.done();
@@ -993,6 +1189,7 @@ mod tests {
         let mut led = digital::Mock::new(&led_expectations);
         let timeout = Milliseconds::new(500);
         let mut delay = delay::NoopDelay::new();
+        let mut eeprom = EmulatedEeprom::default();
 
 
         let _ = embedded_logger::StdLogger::init();
@@ -1010,20 +1207,1123 @@ mod tests {
 
         let mut ser = serial::Mock::new(&serial_transactions);
 
-        let mut bs = BaryonSweeper::new(&mut ser, &mut timer, &mut led, timeout, &mut delay);
-        let mut length = 0;
-        let mut challenge_version = 0;
-        let mut challenge1b = [0u8; 16];
+        let mut bs = BaryonSweeper::new(&mut ser, &mut timer, &mut led, timeout, &mut delay, Responder::new(&mut eeprom, BatteryProfile::default()));
         for _ in 0..3 {
-            bs.sweep_iter(&mut length, &mut  challenge_version, &mut challenge1b);
+            bs.sweep_iter().unwrap();
         }
         ser.done();
         led.done();
 
     }
 
+    /// The eh0 peripherals `BaryonSweeper` runs on, all mocked.
+    struct MockBoard<C = eh0_mock::timer::MockTimer> {
+        serial: eh0_mock::serial::Mock<u8>,
+        timer: C,
+        led: eh0_mock::digital::Mock,
+        delay: eh0_mock::delay::NoopDelay,
+    }
+
+    impl MockBoard {
+        /// Expect `transactions` on the serial port and one LED blink for
+        /// each of the `answered` packets.
+        fn new(transactions: &[eh0_mock::serial::Transaction<u8>], answered: usize) -> Self {
+            Self::with_timer(transactions, answered, eh0_mock::timer::MockClock::new().get_timer())
+        }
+    }
+
+    impl<C: CountDown + Periodic> MockBoard<C> {
+        fn with_timer(transactions: &[eh0_mock::serial::Transaction<u8>], answered: usize, timer: C) -> Self {
+            use eh0_mock::digital::{State, Transaction};
+
+            let _ = embedded_logger::StdLogger::init();
+            let blinks: std::vec::Vec<_> = (0..answered)
+                .flat_map(|_| [Transaction::set(State::Low), Transaction::set(State::High)])
+                .collect();
+            Self {
+                serial: eh0_mock::serial::Mock::new(transactions),
+                timer,
+                led: eh0_mock::digital::Mock::new(&blinks),
+                delay: eh0_mock::delay::NoopDelay::new(),
+            }
+        }
+
+        fn sweeper<E: Eeprom, B: BatterySensors>(
+            &mut self,
+            responder: Responder<E, BuiltinKeys, B>,
+        ) -> BaryonSweeper<'_, eh0_mock::serial::Mock<u8>, C, eh0_mock::digital::Mock, embedded_time::duration::Milliseconds, eh0_mock::delay::NoopDelay, E, BuiltinKeys, B> {
+            BaryonSweeper::new(&mut self.serial, &mut self.timer, &mut self.led, embedded_time::duration::Milliseconds::new(500), &mut self.delay, responder)
+        }
+
+        /// Check every expected transaction took place.
+        fn done(&mut self) {
+            self.serial.done();
+            self.led.done();
+        }
+    }
+
+    #[test]
+    fn test_ehal_mock_eeprom() {
+        use eh0_mock::serial;
+
+        let mut eeprom = EmulatedEeprom::default();
+
+        let cmd_write_eeprom = [0x5A, 0x04, 0x13, 0x18, 0x12, 0x64];
+        let cmd_write_eeprom_response = [0xA5, 0x02, 0x06, 0x52];
+
+        let cmd_read_eeprom = [0x5A, 0x03, 0x14, 0x18, 0x76];
+        let cmd_read_eeprom_response = [0xA5, 0x03, 0x06, 0x12, 0x3F];
+
+        let cmd_read_eeprom_out_of_range = [0x5A, 0x03, 0x14, 0x80, 0x0E];
+        let nak = [0xA5, 0x02, 0x05, 0x53];
+
+        let transactions = [
+            serial::Transaction::read_many(cmd_write_eeprom),
+            serial::Transaction::write_many(cmd_write_eeprom_response),
+            serial::Transaction::read_many(cmd_read_eeprom),
+            serial::Transaction::write_many(cmd_read_eeprom_response),
+            serial::Transaction::read_many(cmd_read_eeprom_out_of_range),
+            serial::Transaction::write_many(nak),
+        ];
+        let mut board = MockBoard::new(&transactions, 3);
+
+        let mut bs = board.sweeper(Responder::new(&mut eeprom, BatteryProfile::default()));
+        bs.sweep_iter().unwrap();
+        bs.sweep_iter().unwrap();
+        assert_eq!(bs.sweep_iter(), Err(Error::EepromAddress(0x80)));
+        board.done();
+
+        assert_eq!(eeprom.read(0x18), Some(0x12));
+        assert_eq!(eeprom.read_word(0x18), Some(0xFF12));
+    }
+
+    #[test]
+    fn test_ehal_mock_custom_profile() {
+        use eh0_mock::serial;
+
+        let cmd_read_capacity = [0x5A, 0x02, 0x07, 0x9C];
+        let cmd_read_capacity_response = [0xA5, 0x04, 0x06, 0xB0, 0x04, 0x9C];
+
+        let cmd_read22 = [0x5A, 0x02, 0x16, 0x8D];
+        let cmd_read22_response = [0xA5, 0x07, 0x06, 0x42, 0x65, 0x6E, 0x63, 0x68, 0x6D];
+
+        let transactions = [
+            serial::Transaction::read_many(cmd_read_capacity),
+            serial::Transaction::write_many(cmd_read_capacity_response),
+            serial::Transaction::read_many(cmd_read22),
+            serial::Transaction::write_many(cmd_read22_response),
+        ];
+        let mut board = MockBoard::new(&transactions, 2);
+
+        let mut profile = BatteryProfile::default().with_manufacturer(b"Bench");
+        profile.capacity = 1200;
+
+        let mut bs = board.sweeper(Responder::new(EmulatedEeprom::default(), profile));
+        for _ in 0..2 {
+            bs.sweep_iter().unwrap();
+        }
+        board.done();
+    }
+
+    #[test]
+    fn test_profile_manufacturer_truncated() {
+        let profile = BatteryProfile::default().with_manufacturer(&[b'x'; MANUFACTURER_MAX_LEN + 1]);
+        assert_eq!(profile.manufacturer(), &[b'x'; MANUFACTURER_MAX_LEN]);
+        assert_eq!(BatteryProfile::default().manufacturer(), b"SonyEnergyDevices");
+    }
+
+    #[test]
+    fn test_battery_model_presets() {
+        for model in BatteryModel::ALL {
+            assert_eq!(BatteryModel::from_name(model.name()), Some(model));
+        }
+        assert_eq!(BatteryModel::from_name("psp9000"), None);
+        assert_eq!(cmd_read_capacity(&BatteryModel::Psp1000_2200mAh.profile()), 2200u16.to_le_bytes());
+        assert_eq!(cmd_read_capacity(&BatteryModel::Slim1200mAh.profile()), 1200u16.to_le_bytes());
+        assert_eq!(cmd_read_capacity(&BatteryModel::Go.profile()), 930u16.to_le_bytes());
+        let slim = BatteryModel::Slim1800mAh.profile();
+        assert_eq!(cmd_read_capacity(&slim), 1800u16.to_le_bytes());
+        assert_eq!(cmd_read_status(&slim), [0x10, 0xc3, 0x06]);
+        assert_eq!(cmd_read13(&slim), [0x9d, 0x10, 0x10, 0x28, 0x14]);
+        assert_eq!(cmd_read22(&slim), b"SonyEnergyDevices");
+    }
+
+    #[test]
+    fn test_cmd_read_serialno() {
+        let service = BatteryProfile::default();
+        assert!(service.is_service_mode());
+        assert_eq!(cmd_read_serialno(&service), [0xFF; 4]);
+
+        let normal = BatteryProfile::default().with_serial_number([0x12, 0x34, 0x56, 0x78]);
+        assert!(!normal.is_service_mode());
+        assert_eq!(cmd_read_serialno(&normal), [0x34, 0x12, 0x78, 0x56]);
+    }
+
+    #[test]
+    fn test_emulated_eeprom_bounds() {
+        let mut eeprom = EmulatedEeprom::erased();
+        assert_eq!(eeprom.read(0x7F), Some(0xFF));
+        assert_eq!(eeprom.read(0x80), None);
+        assert_eq!(eeprom.write(0x80, 0x00), None);
+        assert_eq!(eeprom.write_word(0x7F, 0x1234), None);
+        assert_eq!(eeprom.write_word(0x7E, 0x1234), Some(()));
+        assert_eq!(eeprom.contents()[0x7E..], [0x34, 0x12]);
+    }
+
+    #[test]
+    fn test_ehal_mock_bad_checksum() {
+        use eh0_mock::serial;
+
+        let corrupted_read_status = [0x5A, 0x02, 0x01, 0xA3];
+        let nak = [0xA5, 0x02, 0x05, 0x53];
+        let cmd_read_status = [0x5A, 0x02, 0x01, 0xA2];
+        let cmd_read_status_response = [0xA5, 0x05, 0x06, 0x10, 0xC3, 0x06, 0x76];
+
+        let transactions = [
+            serial::Transaction::read_many(corrupted_read_status),
+            serial::Transaction::write_many(nak),
+            serial::Transaction::read_many(corrupted_read_status),
+            serial::Transaction::read_many(cmd_read_status),
+            serial::Transaction::write_many(cmd_read_status_response),
+        ];
+        let mut board = MockBoard::new(&transactions, 2);
+
+        let mut bs = board.sweeper(Responder::new(EmulatedEeprom::default(), BatteryProfile::default()));
+        assert_eq!(bs.sweep_iter(), Err(Error::Checksum));
+        bs.set_checksum_policy(ChecksumPolicy::Ignore);
+        assert_eq!(bs.sweep_iter(), Err(Error::Checksum));
+        bs.sweep_iter().unwrap();
+        assert_eq!(bs.checksum_errors(), 2);
+        board.done();
+    }
+
+    #[test]
+    fn test_ehal_mock_bad_length_resync() {
+        use eh0_mock::serial;
+
+        let zero_length = [0x5A, 0x00];
+        let one_length = [0x5A, 0x01];
+        let too_long = [0x5A, 0x41];
+        // a stray 0x5A right before a genuine packet
+        let cmd_read_status = [0x5A, 0x5A, 0x02, 0x01, 0xA2];
+        let cmd_read_status_response = [0xA5, 0x05, 0x06, 0x10, 0xC3, 0x06, 0x76];
+
+        let transactions = [
+            serial::Transaction::read_many(zero_length),
+            serial::Transaction::read_many(one_length),
+            serial::Transaction::read_many(too_long),
+            serial::Transaction::read_many(cmd_read_status),
+            serial::Transaction::write_many(cmd_read_status_response),
+        ];
+        let mut board = MockBoard::new(&transactions, 1);
+
+        board.sweeper(Responder::new(EmulatedEeprom::default(), BatteryProfile::default())).sweep_iter().unwrap();
+        board.done();
+    }
+
+    /// A timer that has always already expired, so every read that would
+    /// block times out straight away.
+    struct ExpiredTimer;
+
+    impl CountDown for ExpiredTimer {
+        type Time = embedded_time::duration::Milliseconds;
+
+        fn start<T: Into<Self::Time>>(&mut self, _count: T) {}
+
+        fn wait(&mut self) -> nb::Result<(), void::Void> {
+            Ok(())
+        }
+    }
+
+    impl Periodic for ExpiredTimer {}
+
+    #[test]
+    fn test_ehal_mock_truncated_packet() {
+        use eh0_mock::serial;
+
+        let cmd_read_status = [0x5A, 0x02, 0x01, 0xA2];
+        let cmd_read_status_response = [0xA5, 0x05, 0x06, 0x10, 0xC3, 0x06, 0x76];
+
+        let transactions = [
+            // unplugged right after the header
+            serial::Transaction::read(0x5A),
+            serial::Transaction::read_error(nb::Error::WouldBlock),
+            // unplugged in the middle of the body
+            serial::Transaction::read_many([0x5A, 0x02, 0x01]),
+            serial::Transaction::read_error(nb::Error::WouldBlock),
+            serial::Transaction::read_many(cmd_read_status),
+            serial::Transaction::write_many(cmd_read_status_response),
+        ];
+        let mut board = MockBoard::with_timer(&transactions, 1, ExpiredTimer);
+
+        let mut bs = board.sweeper(Responder::new(EmulatedEeprom::default(), BatteryProfile::default()));
+        assert_eq!(bs.sweep_iter(), Err(Error::TimedOut));
+        assert_eq!(bs.sweep_iter(), Err(Error::TimedOut));
+        bs.sweep_iter().unwrap();
+        board.done();
+    }
+
+    #[test]
+    fn test_build_packet_too_long() {
+        assert!(build_packet(ResponseType::Ack as u8, &[0u8; MAX_PAYLOAD]).is_ok());
+        assert_eq!(build_packet(ResponseType::Ack as u8, &[0u8; MAX_PAYLOAD + 1]), Err(Error::BadLength));
+    }
+
+    #[test]
+    fn test_ehal_mock_auth_out_of_order() {
+        use eh0_mock::serial;
+
+        let nak = [0xA5, 0x02, 0x05, 0x53];
+
+        let cmdauth1_challenge = [0x5A, 0x0B, 0x80, 0xEB, 0xDE, 0x26, 0xFF, 0x72, 0x99, 0xF6, 0x64, 0xFF, 0xC8];
+        let cmdauth1_response = [0xA5, 0x12, 0x06, 0xD6, 0x20, 0x94, 0xBC, 0xE1,
+                                0x73, 0x17, 0xBD, 0x8B, 0x4B, 0xF6, 0x8E, 0xD4, 0xC0, 0x02, 0x03, 0xE1];
+
+        let cmdauth2_challenge = [0x5A, 0x0A, 0x81, 0xE8, 0x60, 0xBF, 0xB1, 0x5F, 0x86, 0x8F, 0x77, 0x77];
+        let cmdauth2_response  = [0xA5, 0x12, 0x06, 0x62, 0x38, 0x37, 0x5D, 0x4D, 0x5E, 0xC0,
+                                  0xEA, 0xCD, 0x3A, 0x74, 0xD4, 0xD9, 0xA0, 0x69, 0x98, 0xF6];
+        let auth2_trailer = [0x5a, 0x02, 0x01, 0xa2];
+
+        let cmdauthgo_challenge = [0x5A, 0x2A, 0x90, 0x20, 0x10, 0x00, 0x06, 0x82, 0x82, 0x82, 0x82, 0xCB, 0xA3, 0xDB, 0xAC, 0x00, 0xDF, 0x26, 0xF8, 0xDD, 0x5B, 0x0D, 0xAC, 0x91, 0x9A, 0xCF, 0x0B, 0x63, 0x26, 0x06, 0x18, 0xE6, 0x30, 0x4F, 0xDF, 0xE1, 0x6C, 0xEE, 0xA5, 0x16, 0x4E, 0x94, 0x15, 0xED];
+
+        let transactions = [
+            // CmdAuth2 before any CmdAuth1
+            serial::Transaction::read_many(cmdauth2_challenge),
+            serial::Transaction::write_many(nak),
+            serial::Transaction::read_many(cmdauth1_challenge),
+            serial::Transaction::write_many(cmdauth1_response),
+            // CmdAuthGo before CmdAuth2
+            serial::Transaction::read_many(cmdauthgo_challenge),
+            serial::Transaction::write_many(nak),
+            serial::Transaction::read_many(cmdauth2_challenge),
+            serial::Transaction::write_many(cmdauth2_response),
+            serial::Transaction::write_many(auth2_trailer),
+            // a replayed CmdAuth2 after the handshake went through
+            serial::Transaction::read_many(cmdauth2_challenge),
+            serial::Transaction::write_many(nak),
+        ];
+        let mut board = MockBoard::new(&transactions, 5);
+
+        let mut bs = board.sweeper(Responder::new(EmulatedEeprom::default(), BatteryProfile::default()));
+        assert_eq!(bs.sweep_iter(), Err(Error::AuthOrder));
+        assert_eq!(bs.responder().auth_state(), AuthState::Idle);
+        bs.sweep_iter().unwrap();
+        assert_eq!(bs.responder().auth_state().version(), Some(0xEB));
+        assert_eq!(bs.sweep_iter(), Err(Error::AuthOrder));
+        assert!(matches!(bs.responder().auth_state(), AuthState::Auth1Done { version: 0xEB, .. }));
+        bs.sweep_iter().unwrap();
+        assert_eq!(bs.responder().auth_state(), AuthState::Auth2Done { version: 0xEB });
+        assert_eq!(bs.sweep_iter(), Err(Error::AuthOrder));
+        assert_eq!(bs.responder().auth_state(), AuthState::Auth2Done { version: 0xEB });
+        board.done();
+    }
+
+    #[test]
+    fn test_responder_auth_go_eb() {
+        let cmdauth1_challenge = [0x5A, 0x0B, 0x80, 0xEB, 0xDE, 0x26, 0xFF, 0x72, 0x99, 0xF6, 0x64, 0xFF, 0xC8];
+        let cmdauth1_response = [0xA5, 0x12, 0x06, 0xD6, 0x20, 0x94, 0xBC, 0xE1,
+                                0x73, 0x17, 0xBD, 0x8B, 0x4B, 0xF6, 0x8E, 0xD4, 0xC0, 0x02, 0x03, 0xE1];
+
+        let cmdauth2_challenge = [0x5A, 0x0A, 0x81, 0xE8, 0x60, 0xBF, 0xB1, 0x5F, 0x86, 0x8F, 0x77, 0x77];
+        let cmdauth2_response  = [0xA5, 0x12, 0x06, 0x62, 0x38, 0x37, 0x5D, 0x4D, 0x5E, 0xC0,
+                                  0xEA, 0xCD, 0x3A, 0x74, 0xD4, 0xD9, 0xA0, 0x69, 0x98, 0xF6];
+
+        let mut responder = Responder::new(EmulatedEeprom::default(), BatteryProfile::default());
+        let mut responses = Frames::new();
+
+        responder.respond(&cmdauth1_challenge[2..12], &mut responses).unwrap();
+        assert_eq!(responses.len(), 1);
+        assert_eq!(responses.iter().next(), Some(&cmdauth1_response[..]));
+
+        responder.respond_packet(&cmdauth2_challenge, &mut responses).unwrap();
+        let frames: Vec<&[u8]> = responses.iter().collect();
+        assert_eq!(frames, [&cmdauth2_response[..], &[0x5a, 0x02, 0x01, 0xa2][..]]);
+        assert_eq!(responder.auth_state(), AuthState::Auth2Done { version: 0xEB });
+    }
+
+    #[test]
+    fn test_responder_malformed_requests() {
+        let mut responder = Responder::new(EmulatedEeprom::default(), BatteryProfile::default());
+        let mut responses = Frames::new();
+
+        assert_eq!(responder.respond(&[], &mut responses), Err(Error::BadLength));
+        assert!(responses.is_empty());
+
+        assert_eq!(responder.respond_packet(&[0x5A, 0x02, 0x01, 0xA3], &mut responses), Err(Error::Checksum));
+        assert_eq!(responses.iter().next(), Some(Frame::nak().as_bytes()));
+
+        assert_eq!(responder.respond_packet(&[0x5A, 0x03, 0x01, 0xA2], &mut responses), Err(Error::BadLength));
+        assert!(responses.is_empty());
+
+        // unknown commands get the general NAK
+        responder.respond(&[0x42], &mut responses).unwrap();
+        assert_eq!(responses.iter().next(), Some(&[0xA5, 0x02, 0x05, 0x53][..]));
+    }
+
     #[test]
     fn test_cmdauth1_invalid_version() {
-        assert!(cmdauth1(0x55, &[0x00; 1]).is_err());
+        assert!(cmdauth1(&mut Ciphers::new(BuiltinKeys), 0x55, &[0x00; 1]).is_err());
+        assert_eq!(cmdauth1(&mut Ciphers::new(BuiltinKeys), 0x55, &[0x00; 8]), Err(Error::UnknownChallengeVersion(0x55)));
+        assert_eq!(cmdauth1(&mut Ciphers::new(BuiltinKeys), 0xD9, &[0x00; 1]), Err(Error::BadLength));
+    }
+
+    #[test]
+    fn test_cmdauth2_invalid_version() {
+        // keys without secrets were dropped from the ring, so 0x0C is simply unknown
+        assert_eq!(cmdauth2(&mut Ciphers::new(BuiltinKeys), 0x0C, &[0x00; 8], &[0x00; 16]), Err(Error::UnknownChallengeVersion(0x0C)));
+    }
+
+    #[test]
+    fn test_cmdauthgo_errors() {
+        assert_eq!(cmdauthgo(&mut Ciphers::new(BuiltinKeys), &[0x00; 39]), Err(Error::BadLength));
+        assert_eq!(cmdauthgo(&mut Ciphers::new(BuiltinKeys), &[0x00; 40]), Err(Error::GoValidation));
+    }
+
+    /// In-memory serial port: reads drain `rx`, writes append to `tx`.
+    struct IoMock {
+        rx: std::collections::VecDeque<u8>,
+        tx: std::vec::Vec<u8>,
+    }
+
+    impl IoMock {
+        fn new(rx: &[u8]) -> Self {
+            Self { rx: rx.iter().copied().collect(), tx: std::vec::Vec::new() }
+        }
+    }
+
+    impl Read<u8> for IoMock {
+        type Error = core::convert::Infallible;
+
+        fn read(&mut self) -> nb::Result<u8, Self::Error> {
+            self.rx.pop_front().ok_or(nb::Error::WouldBlock)
+        }
+    }
+
+    impl Write<u8> for IoMock {
+        type Error = core::convert::Infallible;
+
+        fn write(&mut self, byte: u8) -> nb::Result<(), Self::Error> {
+            self.tx.push(byte);
+            Ok(())
+        }
+
+        fn flush(&mut self) -> nb::Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    #[cfg(feature="eh1")]
+    impl embedded_io::ErrorType for IoMock {
+        type Error = core::convert::Infallible;
+    }
+
+    #[cfg(feature="eh1")]
+    impl embedded_io::Read for IoMock {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+            let mut n = 0;
+            while n < buf.len() {
+                let Some(byte) = self.rx.pop_front() else { break };
+                buf[n] = byte;
+                n += 1;
+            }
+            Ok(n)
+        }
+    }
+
+    #[cfg(feature="eh1")]
+    impl embedded_io::ReadReady for IoMock {
+        fn read_ready(&mut self) -> Result<bool, Self::Error> {
+            Ok(!self.rx.is_empty())
+        }
+    }
+
+    #[cfg(feature="eh1")]
+    impl embedded_io::Write for IoMock {
+        fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+            self.tx.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    #[cfg(feature="eh1")]
+    #[test]
+    fn test_eh1_read_status() {
+        use embedded_hal_mock::eh1::{digital, delay};
+        use fugit::ExtU32;
+
+        let _ = embedded_logger::StdLogger::init();
+
+        let led_expectations = [
+            digital::Transaction::set(digital::State::Low),
+            digital::Transaction::set(digital::State::High),
+            digital::Transaction::set(digital::State::Low),
+            digital::Transaction::set(digital::State::High),
+        ];
+        let mut led = digital::Mock::new(&led_expectations);
+        let mut delay = delay::NoopDelay::new();
+        let mut eeprom = EmulatedEeprom::default();
+
+        let corrupted_read_status = [0x5A, 0x02, 0x01, 0xA3];
+        let cmd_read_status = [0x5A, 0x02, 0x01, 0xA2];
+        let mut ser = IoMock::new(&[corrupted_read_status, cmd_read_status].concat());
+
+        let mut bs = eh1::BaryonSweeper::new(&mut ser, &mut led, 500.millis(), &mut delay, Responder::new(&mut eeprom, BatteryProfile::default()));
+        assert_eq!(bs.sweep_iter(), Err(Error::Checksum));
+        bs.sweep_iter().unwrap();
+        assert_eq!(bs.checksum_errors(), 1);
+        assert_eq!(ser.tx, [0xA5, 0x02, 0x05, 0x53, 0xA5, 0x05, 0x06, 0x10, 0xC3, 0x06, 0x76]);
+        led.done();
+    }
+
+    #[cfg(feature="eh1")]
+    #[test]
+    fn test_eh1_truncated_packet() {
+        use embedded_hal_mock::eh1::{digital, delay};
+        use fugit::ExtU32;
+
+        let _ = embedded_logger::StdLogger::init();
+
+        let mut led = digital::Mock::new(&[]);
+        let mut delay = delay::NoopDelay::new();
+        let mut eeprom = EmulatedEeprom::default();
+
+        let mut ser = IoMock::new(&[0x5A, 0x02, 0x01]);
+
+        let mut bs = eh1::BaryonSweeper::new(&mut ser, &mut led, 500.millis(), &mut delay, Responder::new(&mut eeprom, BatteryProfile::default()));
+        assert_eq!(bs.sweep_iter(), Err(Error::TimedOut));
+        assert!(ser.tx.is_empty());
+        led.done();
+    }
+
+    #[cfg(feature="async")]
+    impl embedded_io_async::Read for IoMock {
+        async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+            if self.rx.is_empty() {
+                // nothing more from syscon, let the timeout fire
+                core::future::pending::<()>().await;
+            }
+            embedded_io::Read::read(self, buf)
+        }
+    }
+
+    #[cfg(feature="async")]
+    impl embedded_io_async::Write for IoMock {
+        async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+            embedded_io::Write::write(self, buf)
+        }
+    }
+
+    #[cfg(feature="async")]
+    #[test]
+    fn test_async_matches_blocking() {
+        use embedded_hal_mock::eh1::{digital, delay};
+        use fugit::ExtU32;
+
+        let _ = embedded_logger::StdLogger::init();
+
+        let cmd_read_status = [0x5A, 0x02, 0x01, 0xA2];
+        let corrupted_read_status = [0x5A, 0x02, 0x01, 0xA3];
+        let cmd_read_eeprom = [0x5A, 0x03, 0x14, 0x7F, 0x0F];
+        let rx = [&cmd_read_status[..], &corrupted_read_status, &cmd_read_eeprom, &[0x5A, 0x02]].concat();
+
+        // read status, the NAK and read EEPROM each blink the LED
+        let led_expectations: std::vec::Vec<_> = (0..3).flat_map(|_| [
+            digital::Transaction::set(digital::State::Low),
+            digital::Transaction::set(digital::State::High),
+        ]).collect();
+
+        let mut blocking_ser = IoMock::new(&rx);
+        let mut led = digital::Mock::new(&led_expectations);
+        let mut delay = delay::NoopDelay::new();
+        let mut eeprom = EmulatedEeprom::default();
+        let mut bs = eh1::BaryonSweeper::new(&mut blocking_ser, &mut led, 500.millis(), &mut delay, Responder::new(&mut eeprom, BatteryProfile::default()));
+        let blocking_results = [bs.sweep_iter(), bs.sweep_iter(), bs.sweep_iter(), bs.sweep_iter()];
+        led.done();
+
+        let mut async_ser = IoMock::new(&rx);
+        let mut led = digital::Mock::new(&led_expectations);
+        let mut delay = delay::NoopDelay::new();
+        let mut eeprom = EmulatedEeprom::default();
+        let mut bs = asynch::BaryonSweeper::new(&mut async_ser, &mut led, 500.millis(), &mut delay, Responder::new(&mut eeprom, BatteryProfile::default()));
+        let async_results = embassy_futures::block_on(async {
+            [bs.sweep_iter().await, bs.sweep_iter().await, bs.sweep_iter().await, bs.sweep_iter().await]
+        });
+        led.done();
+
+        assert_eq!(blocking_results, [Ok(()), Err(Error::Checksum), Ok(()), Err(Error::TimedOut)]);
+        assert_eq!(async_results, blocking_results);
+        assert_eq!(async_ser.tx, blocking_ser.tx);
+        assert_eq!(async_ser.tx, [
+            0xA5, 0x05, 0x06, 0x10, 0xC3, 0x06, 0x76,
+            0xA5, 0x02, 0x05, 0x53,
+            0xA5, 0x03, 0x06, 0xFF, 0x52,
+        ]);
+    }
+
+    /// Deterministic RNG replaying `bytes`, then counting up.
+    struct ReplayRng<'a> {
+        bytes: &'a [u8],
+        counter: u8,
+    }
+
+    impl rand_core::RngCore for ReplayRng<'_> {
+        fn next_u32(&mut self) -> u32 {
+            rand_core::impls::next_u32_via_fill(self)
+        }
+
+        fn next_u64(&mut self) -> u64 {
+            rand_core::impls::next_u64_via_fill(self)
+        }
+
+        fn fill_bytes(&mut self, dest: &mut [u8]) {
+            for byte in dest {
+                *byte = match self.bytes.split_first() {
+                    Some((first, rest)) => {
+                        self.bytes = rest;
+                        *first
+                    },
+                    None => {
+                        self.counter = self.counter.wrapping_add(1);
+                        self.counter
+                    },
+                };
+            }
+        }
+
+        fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand_core::Error> {
+            self.fill_bytes(dest);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_syscon_polls_responder() {
+        let _ = embedded_logger::StdLogger::init();
+        let profile = BatteryProfile::GO.with_serial_number([0x12, 0x34, 0x56, 0x78]);
+
+        for version in consts::KEYRING.versions() {
+            for auth_go in [false, true] {
+                let mut responder = Responder::new(EmulatedEeprom::default(), profile);
+                let mut syscon = SysconEmulator::new(version).unwrap().with_auth_go(auth_go);
+                let mut rng = ReplayRng { bytes: &[], counter: version };
+                let polled = syscon.poll(&mut rng, |request, responses| {
+                    let _ = responder.respond_packet(request.as_bytes(), responses);
+                    Ok(())
+                });
+                assert_eq!(polled, Ok(profile), "version 0x{:02x}", version);
+                assert!(syscon.is_authenticated());
+            }
+        }
+    }
+
+    #[test]
+    fn test_syscon_matches_capture() {
+        let mut rng = ReplayRng { bytes: &[0x8E, 0x35, 0xF3, 0x8F, 0x2B, 0x8C, 0x6D, 0x8F], counter: 0 };
+        let mut syscon = SysconEmulator::new(0xD9).unwrap();
+        let auth1 = syscon.auth1_request(&mut rng).unwrap();
+        assert_eq!(auth1.as_bytes(), [0x5A, 0x0B, 0x80, 0xD9, 0x8E, 0x35, 0xF3, 0x8F, 0x2B, 0x8C, 0x6D, 0x8F, 0x49]);
+
+        let mut responses = Frames::new();
+        responses.push(Frame::from_bytes(&[
+            0xA5, 0x12, 0x06, 0x83, 0x32, 0x32, 0xDE, 0xF3, 0x25, 0xA2,
+            0x7C, 0x1A, 0xC9, 0x21, 0x7A, 0xE9, 0x8F, 0xBE, 0x22, 0x71,
+        ]).unwrap());
+        syscon.verify_auth1(&responses).unwrap();
+
+        let auth2 = syscon.auth2_request().unwrap();
+        assert_eq!(auth2.as_bytes(), [0x5A, 0x0A, 0x81, 0x13, 0xF1, 0x06, 0x0B, 0x97, 0x9E, 0x9F, 0xF9, 0x38]);
+
+        // one flipped bit in the answer, checksum fixed up
+        let mut tampered = [
+            0xA5, 0x12, 0x06, 0xBA, 0x54, 0x76, 0x57, 0x8E, 0xAF, 0x4E,
+            0x8F, 0xAD, 0xF2, 0xA3, 0x55, 0xDA, 0x10, 0xC2, 0x1D, 0xED,
+        ];
+        tampered[10] ^= 1;
+        tampered[19] = checksum(&tampered[..19]);
+        responses.clear();
+        responses.push(Frame::from_bytes(&tampered).unwrap());
+        assert_eq!(syscon.verify_auth2(&responses), Err(Error::BadResponse));
+        assert!(!syscon.is_authenticated());
+        assert_eq!(syscon.auth2_request().unwrap_err(), Error::AuthOrder);
+    }
+
+    #[test]
+    fn test_key_table_new_version() {
+        let keys = BuiltinKeys;
+        let mut table = KeyTable::<2>::new();
+        // the 0xD9 material under a version the built-in tables do not know
+        let entry = VersionKeys {
+            version: 0x42,
+            secret1: keys.secret1(0xD9).unwrap(),
+            secret2: keys.secret2(0xD9).unwrap(),
+            key: keys.key(0xD9).unwrap(),
+        };
+        table.insert(entry).unwrap();
+        assert_eq!(table.insert(entry), Ok(()));
+        assert_eq!(table.versions().count(), 1);
+
+        assert_eq!(SysconEmulator::new(0x42).err(), Some(Error::UnknownChallengeVersion(0x42)));
+        let mut syscon = SysconEmulator::with_keys(0x42, &table).unwrap().with_auth_go(true);
+        let mut responder = Responder::with_keys(EmulatedEeprom::default(), BatteryProfile::default(), &table);
+        let mut rng = ReplayRng { bytes: &[], counter: 0 };
+        let polled = syscon.poll(&mut rng, |request, responses| {
+            let _ = responder.respond_packet(request.as_bytes(), responses);
+            Ok(())
+        });
+        assert_eq!(polled, Ok(BatteryProfile::default()));
+
+        // versions missing from the table are refused like unknown ones
+        let mut responses = Frames::new();
+        let auth1 = build_request(Commands::CmdAuth1 as u8, &[0xD9, 0, 0, 0, 0, 0, 0, 0, 0]).unwrap();
+        assert_eq!(responder.respond_packet(&auth1.0[..auth1.1], &mut responses), Err(Error::UnknownChallengeVersion(0xD9)));
+    }
+
+    #[test]
+    fn test_keyring_lookups() {
+        assert_eq!(consts::KEYRING.versions().count(), 15);
+        assert_eq!(consts::KEYRING.get(0xD9).map(|entry| entry.version), Some(0xD9));
+        // keys without secrets are not usable, so they are not in the ring
+        assert!(!consts::KEYRING.contains(0x0B));
+        assert_eq!(BuiltinKeys.key(0x0C), None);
+        assert_eq!(cmdauth2(&mut Ciphers::new(BuiltinKeys), 0x0B, &[0x00; 8], &[0x00; 16]), Err(Error::UnknownChallengeVersion(0x0B)));
+    }
+
+    #[test]
+    #[should_panic(expected = "duplicate version in KeyRing")]
+    fn test_keyring_rejects_duplicates() {
+        let entry = *consts::KEYRING.get(0xD9).unwrap();
+        KeyRing::new([entry, entry]);
+    }
+
+    #[test]
+    fn test_cipher_cache_switches_versions() {
+        // one responder, so the cached key schedule is replaced every round
+        let mut responder = Responder::new(EmulatedEeprom::default(), BatteryProfile::default());
+        let mut rng = ReplayRng { bytes: &[], counter: 0 };
+        for version in consts::KEYRING.versions().chain([0xD9, 0xEB, 0xD9]) {
+            let mut syscon = SysconEmulator::new(version).unwrap().with_auth_go(true);
+            let polled = syscon.poll(&mut rng, |request, responses| {
+                let _ = responder.respond_packet(request.as_bytes(), responses);
+                Ok(())
+            });
+            assert_eq!(polled, Ok(BatteryProfile::default()), "version 0x{:02x}", version);
+        }
+    }
+
+    #[test]
+    fn test_simulation_discharge_and_charge() {
+        let profile = BatteryProfile::SLIM_1200MAH;
+        let mut simulation = Simulation::new(&profile, 600);
+        let mut readings = profile;
+        simulation.apply(&mut readings);
+        assert_eq!((readings.capacity, readings.voltage, readings.current, readings.time_left), (1200, 4150, 600, 120));
+
+        // an hour at 600mA
+        simulation.advance(3_600_000);
+        simulation.apply(&mut readings);
+        assert_eq!(simulation.level(), 50);
+        assert_eq!((readings.capacity, readings.time_left), (600, 60));
+        assert!(readings.voltage < 4150 && readings.voltage > 3760);
+        assert_eq!(readings.status, profile.status);
+
+        simulation.set_charging(true);
+        simulation.advance(3_600_000);
+        simulation.apply(&mut readings);
+        assert_eq!((readings.capacity, readings.current, readings.time_left), (1200, -1000, 0));
+        assert_eq!(readings.status, profile.status);
+
+        simulation = simulation.with_charging_status([0x10, 0xc3, 0x07]);
+        simulation.apply(&mut readings);
+        assert_eq!(readings.status, [0x10, 0xc3, 0x07]);
+
+        simulation.set_charging(false);
+        simulation.advance(u32::MAX);
+        simulation.advance(u32::MAX);
+        simulation.apply(&mut readings);
+        assert_eq!((readings.capacity, readings.voltage, readings.time_left), (0, 3300, 0));
+    }
+
+    #[test]
+    fn test_ehal_mock_simulation_ticks() {
+        use eh0_mock::serial;
+
+        // a second of silence from syscon, then CmdReadCapacity
+        let mut transactions: std::vec::Vec<_> = (0..100).map(|_| serial::Transaction::read_error(nb::Error::WouldBlock)).collect();
+        transactions.push(serial::Transaction::read_many([0x5A, 0x02, 0x07, 0x9C]));
+        // 1800mAh less 65535mA for a second
+        transactions.push(serial::Transaction::write_many([0xA5, 0x04, 0x06, 0xF5, 0x06, 0x55]));
+        // the next one takes 200ms to come in, which counts too
+        transactions.push(serial::Transaction::read_many([0x5A, 0x02]));
+        transactions.extend((0..20).map(|_| serial::Transaction::read_error(nb::Error::WouldBlock)));
+        transactions.push(serial::Transaction::read_many([0x07, 0x9C]));
+        transactions.push(serial::Transaction::write_many([0xA5, 0x04, 0x06, 0xF2, 0x06, 0x58]));
+        let mut board = MockBoard::new(&transactions, 2);
+
+        let profile = BatteryProfile::default();
+        let mut responder = Responder::new(EmulatedEeprom::default(), profile);
+        responder.set_simulation(Some(Simulation::new(&profile, u16::MAX)));
+        // every poll of `ExpiredTimer` is a tick
+        let mut ticker = ExpiredTimer;
+        let mut bs = board.sweeper(responder).with_ticker(&mut ticker);
+        bs.sweep_iter().unwrap();
+        assert_eq!(bs.responder().profile().capacity, 1781);
+        bs.sweep_iter().unwrap();
+        assert_eq!(bs.responder().profile().capacity, 1778);
+        board.done();
+    }
+
+    /// Sensor mock: fixed readings, counting how often each is taken.
+    #[derive(Default)]
+    struct MockSensors {
+        temperature: Option<u8>,
+        voltage: Option<u16>,
+        current: Option<i16>,
+        reads: usize,
+    }
+
+    impl BatterySensors for MockSensors {
+        fn temperature(&mut self) -> Option<u8> {
+            self.reads += 1;
+            self.temperature
+        }
+
+        fn voltage(&mut self) -> Option<u16> {
+            self.reads += 1;
+            self.voltage
+        }
+
+        fn current(&mut self) -> Option<i16> {
+            self.reads += 1;
+            self.current
+        }
+    }
+
+    #[test]
+    fn test_ehal_mock_sensors() {
+        use eh0_mock::serial;
+
+        let transactions = [
+            // 31°C from the sensor
+            serial::Transaction::read_many([0x5A, 0x02, 0x02, 0xA1]),
+            serial::Transaction::write_many([0xA5, 0x03, 0x06, 0x1F, 0x32]),
+            // 5012mV from the sensor
+            serial::Transaction::read_many([0x5A, 0x02, 0x03, 0xA0]),
+            serial::Transaction::write_many([0xA5, 0x04, 0x06, 0x94, 0x13, 0xA9]),
+            // no current sensor, the profile's 4200mA
+            serial::Transaction::read_many([0x5A, 0x02, 0x04, 0x9F]),
+            serial::Transaction::write_many([0xA5, 0x04, 0x06, 0x68, 0x10, 0xD8]),
+            // other readings do not touch the sensors
+            serial::Transaction::read_many([0x5A, 0x02, 0x01, 0xA2]),
+            serial::Transaction::write_many([0xA5, 0x05, 0x06, 0x10, 0xC3, 0x06, 0x76]),
+        ];
+        let mut board = MockBoard::new(&transactions, 4);
+
+        let mut sensors = MockSensors { temperature: Some(31), voltage: Some(5012), ..Default::default() };
+        let responder = Responder::new(EmulatedEeprom::default(), BatteryProfile::default()).with_sensors(&mut sensors);
+        let mut bs = board.sweeper(responder);
+        for _ in 0..4 {
+            bs.sweep_iter().unwrap();
+        }
+        // measurements are not written back into the profile
+        assert_eq!(bs.responder().profile().temperature, 27);
+        drop(bs);
+        assert_eq!(sensors.reads, 3);
+        board.done();
+    }
+
+    #[test]
+    fn test_passthrough_relays_both_ways() {
+        use embedded_time::duration::Milliseconds;
+        use embedded_hal_mock::eh0::{timer, digital};
+
+        let clock = timer::MockClock::new();
+        let mut timer = clock.get_timer();
+        let led_expectations = [
+            digital::Transaction::set(digital::State::Low),
+            digital::Transaction::set(digital::State::High),
+            digital::Transaction::set(digital::State::High),
+        ];
+        let mut led = digital::Mock::new(&led_expectations);
+
+        // CmdReadStatus, then the Go handshake trailer after a response
+        let requests = [0x5a, 0x02, 0x01, 0xa2];
+        let responses = [0xa5, 0x05, 0x06, 0x10, 0xc3, 0x06, 0x76, 0x5a, 0x02, 0x01, 0xa2];
+        let mut console = IoMock::new(&requests);
+        let mut battery = IoMock::new(&responses);
+        let mut passthrough = Passthrough::new(&mut console, &mut battery, &mut timer, &mut led, Milliseconds::new(500));
+
+        let mut relayed = std::vec::Vec::new();
+        for _ in 0..3 {
+            relayed.push(passthrough.relay_iter().unwrap());
+        }
+        assert_eq!(relayed, [
+            Relayed::Request(Frame::from_bytes(&requests).unwrap()),
+            Relayed::Response(Frame::from_bytes(&responses[..7]).unwrap()),
+            Relayed::Response(Frame::from_bytes(&responses[7..]).unwrap()),
+        ]);
+        assert_eq!(battery.tx, requests);
+        assert_eq!(console.tx, responses);
+        led.done();
+    }
+
+    #[test]
+    fn test_passthrough_forwards_bad_packets() {
+        use embedded_time::duration::Milliseconds;
+        use embedded_hal_mock::eh0::digital;
+
+        let mut timer = ExpiredTimer;
+        let mut led = digital::Mock::new(&[digital::Transaction::set(digital::State::Low)]);
+
+        // bad checksum, then a request cut off after its length byte
+        let requests = [0x5a, 0x02, 0x01, 0xa3, 0x5a, 0x02];
+        let mut console = IoMock::new(&requests);
+        let mut battery = IoMock::new(&[]);
+        let mut passthrough = Passthrough::new(&mut console, &mut battery, &mut timer, &mut led, Milliseconds::new(500));
+
+        assert_eq!(passthrough.relay_iter(), Err(Error::Checksum));
+        assert_eq!(passthrough.relay_iter(), Err(Error::TimedOut));
+        assert_eq!(battery.tx, requests);
+        led.done();
+    }
+
+    #[test]
+    fn test_passthrough_times_out_each_way() {
+        use embedded_time::duration::Milliseconds;
+        use embedded_hal_mock::eh0::digital;
+
+        let mut timer = ExpiredTimer;
+        let mut led = digital::Mock::new(&[]);
+
+        // a request stalls after its length byte while the battery chatters on
+        let mut console = IoMock::new(&[0x5a, 0x02]);
+        let mut battery = IoMock::new(&[0x00; 100]);
+        let mut passthrough = Passthrough::new(&mut console, &mut battery, &mut timer, &mut led, Milliseconds::new(50));
+
+        assert_eq!(passthrough.relay_iter(), Err(Error::TimedOut));
+        assert!(!battery.rx.is_empty());
+        led.done();
+    }
+
+    #[test]
+    fn test_frame_reader_rejects_oversized() {
+        let mut reader = FrameReader::with_headers(&[0xa5]);
+        assert_eq!(reader.feed(0xa5), None);
+        assert_eq!(reader.feed(0x3f), None);
+        assert_eq!(reader.wait(), FrameWait::Header);
+
+        let (packet, len) = build_packet(ResponseType::Ack as u8, &[0x42; MAX_PAYLOAD]).unwrap();
+        let result = packet[..len].iter().find_map(|byte| reader.feed(*byte));
+        assert_eq!(result, Some(Ok(())));
+        assert_eq!(reader.frame().as_bytes(), packet);
+    }
+
+    #[test]
+    fn test_recorder_clones_relayed_battery() {
+        let _ = embedded_logger::StdLogger::init();
+        let profile = BatteryProfile::SLIM_1200MAH
+            .with_serial_number([0x12, 0x34, 0x56, 0x78])
+            .with_manufacturer(b"Sony");
+        let mut original = profile;
+        original.status = [0x90, 0xc4, 0x06];
+        original.read13 = [0x01, 0x02, 0x03, 0x04, 0x05];
+        let mut contents = [0u8; EEPROM_SIZE];
+        for (address, byte) in contents.iter_mut().enumerate() {
+            *byte = address as u8 ^ 0x5a;
+        }
+        let mut battery = Responder::new(EmulatedEeprom::new(contents), original);
+
+        // what a pass-through session sees for syscon's reads
+        let mut recorder = BatteryRecorder::new();
+        let mut requests = std::vec![build_request(0x01, &[]), build_request(0x0c, &[]),
+            build_request(0x0d, &[]), build_request(0x16, &[]), build_request(0x03, &[]), build_request(0x07, &[])];
+        requests.extend((0..EEPROM_SIZE as u8).map(|address| build_request(0x14, &[address])));
+        let mut responses = Frames::new();
+        for (bytes, len) in requests.into_iter().map(Result::unwrap) {
+            let request = Frame::from_bytes(&bytes[..len]).unwrap();
+            battery.respond_packet(request.as_bytes(), &mut responses).unwrap();
+            recorder.record(&Relayed::Request(request)).unwrap();
+            for response in responses.iter() {
+                recorder.record(&Relayed::Response(Frame::from_bytes(response).unwrap())).unwrap();
+            }
+        }
+        assert!(recorder.has_identity());
+        assert!(recorder.is_eeprom_complete());
+        assert_eq!(recorder.profile(), &original);
+        assert_eq!(recorder.eeprom().contents(), &contents);
+
+        // out of range reads are NAKed and leave the image alone
+        let (bytes, len) = build_request(0x14, &[0x80]).unwrap();
+        let request = Frame::from_bytes(&bytes[..len]).unwrap();
+        let _ = battery.respond_packet(request.as_bytes(), &mut responses);
+        assert_eq!(recorder.record_exchange(&request, &responses), Err(Error::Nak));
+
+        // the clone passes syscon's checks with the same answers
+        let mut clone = recorder.into_responder();
+        let mut syscon = SysconEmulator::new(0xD9).unwrap();
+        let mut rng = ReplayRng { bytes: &[], counter: 0 };
+        let polled = syscon.poll(&mut rng, |request, responses| clone.respond_packet(request.as_bytes(), responses));
+        assert_eq!(polled, Ok(original));
+        let dumped = syscon.dump_eeprom(|request, responses| clone.respond_packet(request.as_bytes(), responses));
+        assert_eq!(dumped.unwrap().contents(), &contents);
+    }
+
+    #[test]
+    fn test_recorder_learns_from_syscon() {
+        let mut original = BatteryProfile::GO.with_serial_number([0xaa, 0xbb, 0xcc, 0xdd]);
+        original.read13 = [0x11, 0x22, 0x33, 0x44, 0x55];
+        let mut battery = Responder::new(EmulatedEeprom::new([0x42; EEPROM_SIZE]), original);
+        battery.eeprom_mut().write(0x10, 0x99);
+
+        let mut recorder = BatteryRecorder::new();
+        let mut syscon = SysconEmulator::new(0xEB).unwrap().with_auth_go(true);
+        let mut rng = ReplayRng { bytes: &[], counter: 0 };
+        let mut exchange = |request: &Frame, responses: &mut Frames| {
+            battery.respond_packet(request.as_bytes(), responses)?;
+            recorder.record_exchange(request, responses)
+        };
+        assert_eq!(syscon.poll(&mut rng, &mut exchange), Ok(original));
+        assert!(syscon.dump_eeprom(&mut exchange).is_ok());
+
+        assert!(recorder.has_identity());
+        assert!(recorder.is_eeprom_complete());
+        assert_eq!(recorder.profile(), &original);
+        assert_eq!(recorder.eeprom().read(0x10), Some(0x99));
+        assert_eq!(recorder.eeprom().read(0x11), Some(0x42));
+    }
+
+    #[test]
+    fn test_recorder_bytes_round_trip() {
+        let mut battery = Responder::new(EmulatedEeprom::new([0x42; EEPROM_SIZE]), BatteryProfile::GO.with_manufacturer(b"Sony"));
+        let mut recorder = BatteryRecorder::new();
+        for request in [Request::ReadSerialno, Request::Read22, Request::ReadEeprom { address: 0x7f }] {
+            let request = request.to_frame().unwrap();
+            let mut responses = Frames::new();
+            battery.respond_packet(request.as_bytes(), &mut responses).unwrap();
+            recorder.record_exchange(&request, &responses).unwrap();
+        }
+
+        let bytes = recorder.to_bytes();
+        let restored = BatteryRecorder::from_bytes(&bytes).unwrap();
+        assert_eq!(restored.profile(), recorder.profile());
+        assert_eq!(restored.profile().manufacturer(), b"Sony");
+        assert_eq!(restored.eeprom().contents(), recorder.eeprom().contents());
+        assert!(restored.is_eeprom_recorded(0x7f));
+        assert!(!restored.is_eeprom_recorded(0x7e));
+        assert_eq!(restored.to_bytes(), bytes);
+        assert_eq!(BatteryProfile::from_bytes(&BatteryProfile::GO.to_bytes()), Ok(BatteryProfile::GO));
+
+        assert_eq!(BatteryRecorder::from_bytes(&bytes[..bytes.len() - 1]).err(), Some(Error::BadLength));
+        let mut future = bytes;
+        future[4] += 1;
+        assert_eq!(BatteryRecorder::from_bytes(&future).err(), Some(Error::BadRecording));
+        assert_eq!(BatteryRecorder::from_bytes(b"BSR").err(), Some(Error::BadRecording));
+    }
+
+    #[test]
+    fn test_timeout_millis() {
+        assert_eq!(<fugit::NanosDurationU32 as Timeout>::millis(10), fugit::NanosDurationU32::millis(10));
+        assert_eq!(<fugit::MicrosDurationU64 as Timeout>::millis(500), fugit::MicrosDurationU64::millis(500));
+        assert_eq!(<fugit::MillisDurationU32 as Timeout>::millis(5000), fugit::MillisDurationU32::secs(5));
+        assert_eq!(<fugit::NanosDurationU32 as Timeout>::millis(5000), fugit::NanosDurationU32::from_ticks(u32::MAX));
+        assert_eq!(<core::time::Duration as Timeout>::millis(10), core::time::Duration::from_millis(10));
+        assert_eq!(<embedded_time::duration::Milliseconds as Timeout>::millis(10), embedded_time::duration::Milliseconds(10u32));
+
+        assert_eq!(Timeout::to_millis(&fugit::NanosDurationU32::millis(10)), 10);
+        assert_eq!(Timeout::to_millis(&fugit::MicrosDurationU64::secs(5)), 5000);
+        assert_eq!(Timeout::to_millis(&fugit::SecsDurationU64::secs(u64::MAX)), u32::MAX);
+        assert_eq!(Timeout::to_millis(&core::time::Duration::from_micros(1500)), 1);
+    }
+
+    #[test]
+    fn test_codec_requests_round_trip() {
+        let auth1 = [0x5A, 0x0B, 0x80, 0xD9, 0x8E, 0x35, 0xF3, 0x8F, 0x2B, 0x8C, 0x6D, 0x8F, 0x49];
+        assert_eq!(
+            Request::from_frame(&auth1),
+            Ok(Request::Auth1 { version: 0xD9, challenge: [0x8E, 0x35, 0xF3, 0x8F, 0x2B, 0x8C, 0x6D, 0x8F] }),
+        );
+
+        let requests = [
+            Request::ReadStatus,
+            Request::ReadTemperature,
+            Request::ReadVoltage,
+            Request::ReadCurrent,
+            Request::ReadCapacity,
+            Request::Read8,
+            Request::ReadTimeLeft,
+            Request::Read11,
+            Request::ReadSerialno,
+            Request::Read13,
+            Request::WriteEeprom { address: 0x10, value: 0x99 },
+            Request::ReadEeprom { address: 0x7f },
+            Request::Read22,
+            Request::Auth1 { version: 0xD9, challenge: [0x8E, 0x35, 0xF3, 0x8F, 0x2B, 0x8C, 0x6D, 0x8F] },
+            Request::Auth2 { challenge: [0x13, 0xF1, 0x06, 0x0B, 0x97, 0x9E, 0x9F, 0xF9] },
+            Request::AuthGo { data: [0x5a; 40] },
+            Request::Other { command: 0x30, args: RawPayload::from_slice(&[1, 2, 3]).unwrap() },
+        ];
+        for request in requests {
+            let frame = request.to_frame().unwrap();
+            assert_eq!(Request::from_frame(frame.as_bytes()), Ok(request.clone()), "{:?}", request);
+        }
+        assert_eq!(Request::from_frame(&auth1).unwrap().to_frame().unwrap().as_bytes(), auth1);
+        assert_eq!(Request::ReadStatus.to_frame().unwrap().as_bytes(), [0x5a, 0x02, 0x01, 0xa2]);
+    }
+
+    #[test]
+    fn test_codec_decodes_responder() {
+        let mut profile = BatteryProfile::GO.with_serial_number([0x12, 0x34, 0x56, 0x78]);
+        profile.read13 = [0x11, 0x22, 0x33, 0x44, 0x55];
+        let mut responder = Responder::new(EmulatedEeprom::new([0x42; EEPROM_SIZE]), profile);
+        let mut answer = |request: &Request| {
+            let mut responses = Frames::new();
+            responder.respond_packet(request.to_frame().unwrap().as_bytes(), &mut responses).unwrap();
+            let response = Response::from_frame(request, responses.iter().next().unwrap()).unwrap();
+            assert_eq!(response.to_frame().unwrap().as_bytes(), responses.iter().next().unwrap());
+            response
+        };
+
+        assert_eq!(answer(&Request::ReadStatus), Response::Status(profile.status));
+        assert_eq!(answer(&Request::ReadVoltage), Response::Voltage(profile.voltage));
+        assert_eq!(answer(&Request::ReadCapacity), Response::Capacity(profile.capacity));
+        assert_eq!(answer(&Request::ReadSerialno), Response::Serialno(profile.serial_number));
+        assert_eq!(answer(&Request::Read13), Response::Read13(profile.read13));
+        assert_eq!(answer(&Request::ReadEeprom { address: 0x10 }), Response::Eeprom(0x42));
+        assert_eq!(answer(&Request::WriteEeprom { address: 0x10, value: 0x99 }), Response::Ack);
+        assert_eq!(answer(&Request::ReadEeprom { address: 0x10 }), Response::Eeprom(0x99));
+        assert!(matches!(answer(&Request::Read22), Response::Read22(name) if !name.is_empty()));
+
+        // an unknown challenge version gets the short answer
+        let auth1 = Request::Auth1 { version: 0x0B, challenge: [0x8E; 8] };
+        let mut responses = Frames::new();
+        assert_eq!(
+            responder.respond_packet(auth1.to_frame().unwrap().as_bytes(), &mut responses),
+            Err(Error::UnknownChallengeVersion(0x0B))
+        );
+        let refused = responses.iter().next().unwrap();
+        assert_eq!(Response::from_frame(&auth1, refused), Ok(Response::Auth1Refused([0xff; 8])));
+        assert_eq!(Response::Auth1Refused([0xff; 8]).to_frame().unwrap().as_bytes(), refused);
+    }
+
+    #[test]
+    fn test_codec_errors() {
+        let status = [0xA5, 0x05, 0x06, 0x10, 0xC3, 0x06, 0x76];
+        assert_eq!(Response::from_frame(&Request::ReadStatus, &status), Ok(Response::Status([0x10, 0xC3, 0x06])));
+        assert_eq!(Response::from_frame(&Request::ReadVoltage, &status), Err(Error::BadResponse));
+
+        let mut corrupted = status;
+        corrupted[3] ^= 1;
+        assert_eq!(Response::from_frame(&Request::ReadStatus, &corrupted), Err(Error::Checksum));
+        assert_eq!(Response::from_frame(&Request::ReadStatus, &status[..6]), Err(Error::BadLength));
+        assert_eq!(Response::from_frame(&Request::ReadStatus, Frame::nak().as_bytes()), Ok(Response::Nak));
+        assert_eq!(Response::Nak.to_frame().unwrap(), Frame::nak());
+
+        assert_eq!(Request::from_frame(&status), Err(Error::BadLength));
+        assert_eq!(Request::from_frame(&[0x5a, 0x04, 0x0c, 0x10, 0x11, 0x74]), Err(Error::BadLength));
+        let longest = Request::Other { command: 0x30, args: RawPayload::from_slice(&[0; MAX_PAYLOAD]).unwrap() };
+        assert_eq!(Request::from_frame(longest.to_frame().unwrap().as_bytes()), Ok(longest));
     }
 }
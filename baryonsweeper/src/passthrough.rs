@@ -0,0 +1,186 @@
+//! Pass-through mode: sit between a PSP and a genuine battery and log the
+//! traffic, e.g. to capture reference traces or to see why the console
+//! rejects a battery.
+//!
+//! Every byte is forwarded as soon as it arrives, so the battery answers
+//! with its own timing. Each direction times out on its own, so a stalled
+//! packet one way is noticed however busy the other way is. The packets
+//! are only decoded on the side, with `Request` and `Response`.
+
+use embedded_hal::{serial::{Read, Write}, timer::{CountDown, Periodic}, digital::v2::OutputPin};
+use nb::block;
+
+use crate::{fmt_packet, Error, Frame, FrameReader, FrameWait, Request, Response, Timeout, TICK_MS};
+
+use log::info;
+
+/// One packet that went through.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Relayed {
+    /// Sent by the console to the battery.
+    Request(Frame),
+    /// Sent by the battery to the console, including the packet the PSP Go
+    /// batteries append to `CmdAuth2`.
+    Response(Frame),
+}
+
+/// One direction of the relay.
+struct Leg {
+    reader: FrameReader,
+    /// Time since the last byte, in ms.
+    silent_ms: u32,
+}
+
+impl Leg {
+    fn new(reader: FrameReader) -> Self {
+        Self { reader, silent_ms: 0 }
+    }
+
+    fn feed(&mut self, byte: u8) -> Option<Result<(), Error>> {
+        self.silent_ms = 0;
+        self.reader.feed(byte)
+    }
+
+    /// Count a tick. Returns whether it cut a packet short.
+    fn tick(&mut self, timeout_ms: u32) -> bool {
+        self.silent_ms = self.silent_ms.saturating_add(TICK_MS);
+        if self.reader.wait() == FrameWait::InterByte && self.silent_ms >= timeout_ms {
+            self.reader.truncated();
+            return true
+        }
+        false
+    }
+}
+
+/// `timer` must be periodic, it ticks every `TICK_MS` while relaying.
+pub struct Passthrough<'a, S, R, C, P, T>
+where
+    S: Read<u8> + Write<u8>,
+    R: Read<u8> + Write<u8>,
+    C: CountDown + Periodic,
+    P: OutputPin,
+    T: Timeout,
+{
+    console: &'a mut S,
+    battery: &'a mut R,
+    timer: &'a mut C,
+    led_pin: &'a mut P,
+    timeout: T,
+    requests: Leg,
+    responses: Leg,
+    ticking: bool,
+    /// Last request seen, to decode the answer with.
+    request: Option<Request>,
+}
+
+impl<'a, S, R, C, P, T> Passthrough<'a, S, R, C, P, T>
+where
+    S: Read<u8> + Write<u8>,
+    R: Read<u8> + Write<u8>,
+    C: CountDown + Periodic,
+    P: OutputPin,
+    T: Timeout,
+    <C as CountDown>::Time: From<T>,
+{
+    /// `console` is wired to syscon, `battery` to the real battery.
+    /// `timeout` is the longest gap allowed between two bytes of a packet.
+    pub fn new(console: &'a mut S, battery: &'a mut R, timer: &'a mut C, led_pin: &'a mut P, timeout: T) -> Self {
+        Self {
+            console,
+            battery,
+            timer,
+            led_pin,
+            timeout,
+            requests: Leg::new(FrameReader::new()),
+            responses: Leg::new(FrameReader::with_headers(&[0xa5, 0x5a])),
+            ticking: false,
+            request: None,
+        }
+    }
+
+    pub fn relay(&mut self) -> ! {
+        info!("Relaying between console and battery");
+        loop {
+            if let Err(e) = self.relay_iter() {
+                info!("Relay error: {}", e);
+            }
+        }
+    }
+
+    /// Forward bytes both ways until a packet is complete.
+    ///
+    /// A packet with a bad checksum is forwarded like any other and
+    /// reported as `Error::Checksum`; a packet cut short as
+    /// `Error::TimedOut`.
+    pub fn relay_iter(&mut self) -> Result<Relayed, Error> {
+        if !self.ticking {
+            self.timer.start(T::millis(TICK_MS));
+            self.ticking = true;
+        }
+
+        loop {
+            if let Some(byte) = poll(self.console)? {
+                block!(self.battery.write(byte)).map_err(|_| Error::Serial)?;
+                if let Some(result) = self.requests.feed(byte) {
+                    self.led_pin.set_low().map_err(|_| Error::Pin)?;
+                    let frame = self.requests.reader.frame();
+                    self.log_request(&frame);
+                    return result.map(|()| Relayed::Request(frame))
+                }
+            }
+
+            if let Some(byte) = poll(self.battery)? {
+                block!(self.console.write(byte)).map_err(|_| Error::Serial)?;
+                if let Some(result) = self.responses.feed(byte) {
+                    self.led_pin.set_high().map_err(|_| Error::Pin)?;
+                    let frame = self.responses.reader.frame();
+                    self.log_response(&frame);
+                    return result.map(|()| Relayed::Response(frame))
+                }
+            }
+
+            if self.timer.wait().is_ok() {
+                let timeout_ms = self.timeout.to_millis();
+                let requests_cut = self.requests.tick(timeout_ms);
+                let responses_cut = self.responses.tick(timeout_ms);
+                if requests_cut || responses_cut {
+                    return Err(Error::TimedOut)
+                }
+            }
+        }
+    }
+
+    fn log_request(&mut self, frame: &Frame) {
+        let packet = frame.as_bytes();
+        info!("Console -> battery: {}", fmt_packet(packet, packet.len()).as_str());
+        self.request = Request::from_frame(packet).ok();
+        match &self.request {
+            Some(request) => info!("  {:?}", request),
+            None => info!("  malformed request"),
+        }
+    }
+
+    fn log_response(&mut self, frame: &Frame) {
+        let packet = frame.as_bytes();
+        info!("Battery -> console: {}", fmt_packet(packet, packet.len()).as_str());
+        // the packet following the PSP Go `CmdAuth2` answer
+        if packet[0] == 0x5a {
+            return
+        }
+        let Some(request) = &self.request else {
+            return
+        };
+        match Response::from_frame(request, packet) {
+            Ok(response) => info!("  {:?}", response),
+            Err(e) => info!("  unexpected answer to {:?}: {}", request, e),
+        }
+    }
+}
+
+fn poll<S: Read<u8>>(serial: &mut S) -> Result<Option<u8>, Error> {
+    match serial.read() {
+        Ok(byte) => Ok(Some(byte)),
+        Err(nb::Error::WouldBlock) => Ok(None),
+        Err(nb::Error::Other(_e)) => Err(Error::Serial),
+    }
+}
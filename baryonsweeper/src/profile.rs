@@ -0,0 +1,225 @@
+use crate::consts::SERIALNO;
+use crate::Error;
+
+/// Serial number of a service mode ("Pandora") battery.
+pub const SERVICE_SERIALNO: [u8; 4] = SERIALNO;
+
+/// Longest manufacturer string `CmdRead22` can return.
+pub const MANUFACTURER_MAX_LEN: usize = 32;
+
+/// Telemetry reported to syscon by the emulated battery.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BatteryProfile {
+    /// Raw `CmdReadStatus` bytes.
+    pub status: [u8; 3],
+    /// `CmdReadTemperature`, in °C.
+    pub temperature: u8,
+    /// `CmdReadVoltage`, in mV.
+    pub voltage: u16,
+    /// `CmdReadCurrent`, in mA.
+    pub current: i16,
+    /// `CmdReadCapacity`, remaining capacity in mAh.
+    pub capacity: u16,
+    /// `CmdRead8` word, meaning unknown.
+    pub read8: u16,
+    /// `CmdReadTimeLeft`, in minutes.
+    pub time_left: u16,
+    /// `CmdRead11` word, meaning unknown.
+    pub read11: u16,
+    /// Raw `CmdRead13` block.
+    pub read13: [u8; 5],
+    /// Serial number as stored in the pack. `CmdReadSerialno` returns it
+    /// with both 16-bit halves byte swapped.
+    pub serial_number: [u8; 4],
+    manufacturer: [u8; MANUFACTURER_MAX_LEN],
+    manufacturer_len: u8,
+}
+
+impl BatteryProfile {
+    pub const fn new() -> Self {
+        Self {
+            status: [0x10, 0xc3, 0x06],
+            temperature: 27,
+            voltage: 4150,
+            current: 4200,
+            capacity: 1800,
+            read8: 1250,
+            time_left: 1025,
+            read11: 15,
+            read13: [0x9d, 0x10, 0x10, 0x28, 0x14],
+            serial_number: SERVICE_SERIALNO,
+            manufacturer: [0u8; MANUFACTURER_MAX_LEN],
+            manufacturer_len: 0,
+        }.with_manufacturer(b"SonyEnergyDevices")
+    }
+
+    pub const PSP_1000_2200MAH: BatteryProfile = BatteryProfile::new().with_capacity(2200);
+
+    pub const SLIM_1200MAH: BatteryProfile = BatteryProfile::new().with_capacity(1200);
+
+    /// The pack the default values were captured from.
+    pub const SLIM_1800MAH: BatteryProfile = BatteryProfile::new();
+
+    pub const GO: BatteryProfile = BatteryProfile::new().with_capacity(930);
+
+    pub const fn with_capacity(mut self, capacity: u16) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    pub const fn with_serial_number(mut self, serial_number: [u8; 4]) -> Self {
+        self.serial_number = serial_number;
+        self
+    }
+
+    /// Whether the console will boot into service mode with this battery
+    /// instead of treating it as an ordinary pack.
+    pub fn is_service_mode(&self) -> bool {
+        self.serial_number == SERVICE_SERIALNO
+    }
+
+    /// Set the `CmdRead22` manufacturer string, truncated to
+    /// `MANUFACTURER_MAX_LEN` bytes.
+    pub const fn with_manufacturer(mut self, name: &[u8]) -> Self {
+        let len = if name.len() > MANUFACTURER_MAX_LEN {
+            MANUFACTURER_MAX_LEN
+        } else {
+            name.len()
+        };
+        self.manufacturer = [0u8; MANUFACTURER_MAX_LEN];
+        let mut i = 0;
+        while i < len {
+            self.manufacturer[i] = name[i];
+            i += 1;
+        }
+        self.manufacturer_len = len as u8;
+        self
+    }
+
+    pub fn manufacturer(&self) -> &[u8] {
+        &self.manufacturer[..self.manufacturer_len as usize]
+    }
+
+    /// Length of `to_bytes`.
+    pub const BYTES: usize = 26 + MANUFACTURER_MAX_LEN;
+
+    /// Every field in declaration order, words little endian, the
+    /// manufacturer as its length and all `MANUFACTURER_MAX_LEN` bytes.
+    /// The layout is stable, see `from_bytes`.
+    pub fn to_bytes(&self) -> [u8; Self::BYTES] {
+        let fields: [&[u8]; 12] = [
+            &self.status,
+            &[self.temperature],
+            &self.voltage.to_le_bytes(),
+            &self.current.to_le_bytes(),
+            &self.capacity.to_le_bytes(),
+            &self.read8.to_le_bytes(),
+            &self.time_left.to_le_bytes(),
+            &self.read11.to_le_bytes(),
+            &self.read13,
+            &self.serial_number,
+            &[self.manufacturer_len],
+            &self.manufacturer,
+        ];
+        let mut bytes = [0u8; Self::BYTES];
+        let mut pos = 0;
+        for field in fields {
+            bytes[pos..pos + field.len()].copy_from_slice(field);
+            pos += field.len();
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() != Self::BYTES {
+            return Err(Error::BadLength)
+        }
+        let mut fields = Fields(bytes);
+        let profile = Self {
+            status: fields.take(),
+            temperature: u8::from_le_bytes(fields.take()),
+            voltage: u16::from_le_bytes(fields.take()),
+            current: i16::from_le_bytes(fields.take()),
+            capacity: u16::from_le_bytes(fields.take()),
+            read8: u16::from_le_bytes(fields.take()),
+            time_left: u16::from_le_bytes(fields.take()),
+            read11: u16::from_le_bytes(fields.take()),
+            read13: fields.take(),
+            serial_number: fields.take(),
+            manufacturer_len: u8::from_le_bytes(fields.take()),
+            manufacturer: fields.take(),
+        };
+        if profile.manufacturer_len as usize > MANUFACTURER_MAX_LEN {
+            return Err(Error::BadRecording)
+        }
+        Ok(profile)
+    }
+}
+
+/// Reads the fields of `BatteryProfile::to_bytes` one after the other.
+struct Fields<'a>(&'a [u8]);
+
+impl Fields<'_> {
+    /// The caller checks the length up front.
+    fn take<const N: usize>(&mut self) -> [u8; N] {
+        let (field, rest) = self.0.split_at(N);
+        self.0 = rest;
+        let mut bytes = [0u8; N];
+        bytes.copy_from_slice(field);
+        bytes
+    }
+}
+
+/// Battery packs with a built-in preset.
+///
+/// Only the Slim 1800mAh pack has been captured so far. The other presets
+/// differ in capacity and reuse its status, `CmdRead13` and manufacturer
+/// values; a pack at hand can be cloned exactly with `BatteryRecorder`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BatteryModel {
+    /// PSP-1000 extended battery, 2200mAh.
+    Psp1000_2200mAh,
+    /// PSP-2000/3000 standard battery, 1200mAh.
+    Slim1200mAh,
+    /// PSP-2000/3000 extended battery, 1800mAh.
+    Slim1800mAh,
+    /// PSP Go internal battery, 930mAh.
+    Go,
+}
+
+impl BatteryModel {
+    pub const ALL: [BatteryModel; 4] = [
+        BatteryModel::Psp1000_2200mAh,
+        BatteryModel::Slim1200mAh,
+        BatteryModel::Slim1800mAh,
+        BatteryModel::Go,
+    ];
+
+    pub const fn profile(self) -> BatteryProfile {
+        match self {
+            BatteryModel::Psp1000_2200mAh => BatteryProfile::PSP_1000_2200MAH,
+            BatteryModel::Slim1200mAh => BatteryProfile::SLIM_1200MAH,
+            BatteryModel::Slim1800mAh => BatteryProfile::SLIM_1800MAH,
+            BatteryModel::Go => BatteryProfile::GO,
+        }
+    }
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            BatteryModel::Psp1000_2200mAh => "psp1000-2200",
+            BatteryModel::Slim1200mAh => "slim-1200",
+            BatteryModel::Slim1800mAh => "slim-1800",
+            BatteryModel::Go => "go",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<BatteryModel> {
+        Self::ALL.into_iter().find(|model| model.name() == name)
+    }
+}
+
+impl Default for BatteryProfile {
+    fn default() -> Self {
+        Self::new()
+    }
+}
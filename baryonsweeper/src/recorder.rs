@@ -0,0 +1,190 @@
+use crate::syscon::{answer, store};
+use crate::{BatteryProfile, EmulatedEeprom, Error, Frame, Frames, Relayed, Request, Responder, Response, EEPROM_SIZE};
+
+use log::info;
+
+const STATUS: u8 = 1 << 0;
+const SERIALNO: u8 = 1 << 1;
+const READ13: u8 = 1 << 2;
+const READ22: u8 = 1 << 3;
+const IDENTITY: u8 = STATUS | SERIALNO | READ13 | READ22;
+
+/// Start of a recording, followed by the format version.
+const MAGIC: [u8; 4] = *b"BSRC";
+const FORMAT_VERSION: u8 = 1;
+
+/// Learns a genuine battery from its answers, so the emulator can take
+/// over its identity.
+///
+/// Feed it the traffic of a `Passthrough` session with `record`, or the
+/// exchanges of a `SysconEmulator` with `record_exchange`. The status,
+/// serial number, `CmdRead13`/`CmdRead22` blocks and telemetry go into a
+/// `BatteryProfile`, every EEPROM byte read or written into an EEPROM
+/// image. The handshake is not recorded, the emulator still answers it
+/// from the keys.
+///
+/// `to_bytes` saves what has been learned so far, e.g. to a file, and
+/// `from_bytes` restores it to serve the battery later.
+pub struct BatteryRecorder {
+    profile: BatteryProfile,
+    eeprom: EmulatedEeprom,
+    /// One bit per EEPROM address seen.
+    eeprom_recorded: u128,
+    identity: u8,
+    request: Option<Frame>,
+}
+
+impl BatteryRecorder {
+    /// Start from the default profile and an erased EEPROM, which stand in
+    /// for anything the battery is never asked about.
+    pub fn new() -> Self {
+        Self::with_profile(BatteryProfile::new())
+    }
+
+    pub fn with_profile(profile: BatteryProfile) -> Self {
+        Self {
+            profile,
+            eeprom: EmulatedEeprom::erased(),
+            eeprom_recorded: 0,
+            identity: 0,
+            request: None,
+        }
+    }
+
+    /// Record one relayed packet. Responses are matched with the last
+    /// request; a NAK or a malformed answer is reported and not recorded.
+    pub fn record(&mut self, relayed: &Relayed) -> Result<(), Error> {
+        match relayed {
+            Relayed::Request(frame) => {
+                self.request = Some(*frame);
+                Ok(())
+            },
+            // the packet following the PSP Go `CmdAuth2` answer
+            Relayed::Response(frame) if frame.as_bytes()[0] == 0x5a => Ok(()),
+            Relayed::Response(frame) => match self.request.take() {
+                Some(request) => self.learn(request.as_bytes(), frame.as_bytes()),
+                None => Ok(()),
+            },
+        }
+    }
+
+    /// Record a request and the answers to it, e.g. from the `exchange`
+    /// closure of `SysconEmulator::poll`.
+    pub fn record_exchange(&mut self, request: &Frame, responses: &Frames) -> Result<(), Error> {
+        self.record(&Relayed::Request(*request))?;
+        for response in responses.iter() {
+            self.record(&Relayed::Response(Frame::from_bytes(response)?))?;
+        }
+        Ok(())
+    }
+
+    fn learn(&mut self, request: &[u8], response: &[u8]) -> Result<(), Error> {
+        let request = Request::from_frame(request)?;
+        if let Request::Other { .. } = request {
+            return Ok(())
+        }
+        match (&request, answer(&request, response)?) {
+            (Request::ReadEeprom { address }, Response::Eeprom(value)) => self.learn_eeprom(*address, value),
+            (Request::WriteEeprom { address, value }, _) => self.learn_eeprom(*address, *value),
+            (Request::Auth1 { .. } | Request::Auth2 { .. } | Request::AuthGo { .. }, _) => Ok(()),
+            (_, response) => {
+                store(&response, &mut self.profile)?;
+                self.identity |= match response {
+                    Response::Status(_) => STATUS,
+                    Response::Serialno(_) => SERIALNO,
+                    Response::Read13(_) => READ13,
+                    Response::Read22(_) => READ22,
+                    _ => 0,
+                };
+                Ok(())
+            },
+        }
+    }
+
+    fn learn_eeprom(&mut self, address: u8, value: u8) -> Result<(), Error> {
+        if address as usize >= EEPROM_SIZE {
+            return Err(Error::EepromAddress(address))
+        }
+        if !self.is_eeprom_recorded(address) {
+            info!("Recorded EEPROM 0x{:02x} = 0x{:02x}", address, value);
+        }
+        self.eeprom.contents_mut()[address as usize] = value;
+        self.eeprom_recorded |= 1 << address;
+        Ok(())
+    }
+
+    pub fn profile(&self) -> &BatteryProfile {
+        &self.profile
+    }
+
+    pub fn eeprom(&self) -> &EmulatedEeprom {
+        &self.eeprom
+    }
+
+    pub fn is_eeprom_recorded(&self, address: u8) -> bool {
+        (address as usize) < EEPROM_SIZE && self.eeprom_recorded & (1 << address) != 0
+    }
+
+    /// Whether every EEPROM byte has been seen.
+    pub fn is_eeprom_complete(&self) -> bool {
+        self.eeprom_recorded == u128::MAX
+    }
+
+    /// Whether the status, serial number, `CmdRead13` and `CmdRead22`
+    /// answers have all been seen.
+    pub fn has_identity(&self) -> bool {
+        self.identity == IDENTITY
+    }
+
+    /// A responder serving the recorded battery.
+    pub fn into_responder(self) -> Responder<EmulatedEeprom> {
+        Responder::new(self.eeprom, self.profile)
+    }
+
+    /// Length of `to_bytes`.
+    pub const BYTES: usize = MAGIC.len() + 1 + BatteryProfile::BYTES + 1 + 16 + EEPROM_SIZE;
+
+    /// The recording in a stable format: a magic and version, the
+    /// profile's `to_bytes`, which identity answers were seen, the
+    /// recorded EEPROM addresses as a little endian bit mask and the
+    /// EEPROM image.
+    pub fn to_bytes(&self) -> [u8; Self::BYTES] {
+        let mut bytes = [0u8; Self::BYTES];
+        let (header, rest) = bytes.split_at_mut(MAGIC.len() + 1);
+        header[..MAGIC.len()].copy_from_slice(&MAGIC);
+        header[MAGIC.len()] = FORMAT_VERSION;
+        let (profile, rest) = rest.split_at_mut(BatteryProfile::BYTES);
+        profile.copy_from_slice(&self.profile.to_bytes());
+        rest[0] = self.identity;
+        rest[1..17].copy_from_slice(&self.eeprom_recorded.to_le_bytes());
+        rest[17..].copy_from_slice(self.eeprom.contents());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.get(..MAGIC.len()) != Some(&MAGIC[..]) || bytes.get(MAGIC.len()) != Some(&FORMAT_VERSION) {
+            return Err(Error::BadRecording)
+        }
+        if bytes.len() != Self::BYTES {
+            return Err(Error::BadLength)
+        }
+        let (profile, rest) = bytes[MAGIC.len() + 1..].split_at(BatteryProfile::BYTES);
+        let mut eeprom_recorded = [0u8; 16];
+        eeprom_recorded.copy_from_slice(&rest[1..17]);
+        let mut contents = [0u8; EEPROM_SIZE];
+        contents.copy_from_slice(&rest[17..]);
+        Ok(Self {
+            profile: BatteryProfile::from_bytes(profile)?,
+            eeprom: EmulatedEeprom::new(contents),
+            eeprom_recorded: u128::from_le_bytes(eeprom_recorded),
+            identity: rest[0] & IDENTITY,
+            request: None,
+        })
+    }
+}
+
+impl Default for BatteryRecorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,352 @@
+use core::convert::TryInto;
+
+use crate::cipher::Ciphers;
+use crate::{
+    build_packet, cmd_read11, cmd_read13, cmd_read22, cmd_read8, cmd_read_capacity,
+    cmd_read_current, cmd_read_eeprom, cmd_read_serialno, cmd_read_status, cmd_read_temperature,
+    cmd_read_time_left, cmd_read_voltage, cmd_write_eeprom, cmdauth1, cmdauth2, cmdauthgo,
+    verify_checksum, AuthState, BatteryProfile, BatterySensors, BuiltinKeys, Commands, Eeprom,
+    Error, KeyStore, NoSensors, ResponseType, Simulation,
+};
+
+use log::info;
+
+/// Packet sent after a successful `CmdAuth2` for the PSP Go challenge versions.
+pub(crate) const AUTH2_TRAILER: [u8; 4] = [0x5a, 0x02, 0x01, 0xa2];
+
+/// Whether `CmdAuth2` for this challenge version is followed by `AUTH2_TRAILER`.
+pub(crate) fn sends_auth2_trailer(version: u8) -> bool {
+    version == 0xeb || version == 0xb3
+}
+
+/// A single packet on the wire, header and checksum included.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Frame {
+    bytes: [u8; 64],
+    len: usize,
+}
+
+impl Frame {
+    pub(crate) fn new(bytes: [u8; 64], len: usize) -> Self {
+        Self { bytes, len }
+    }
+
+    fn from_slice(packet: &[u8]) -> Self {
+        let mut bytes = [0u8; 64];
+        bytes[..packet.len()].copy_from_slice(packet);
+        Self::new(bytes, packet.len())
+    }
+
+    /// Wrap a packet read off the wire, e.g. a real battery's answer.
+    pub fn from_bytes(packet: &[u8]) -> Result<Self, Error> {
+        if packet.len() > 64 {
+            return Err(Error::BadLength)
+        }
+        Ok(Self::from_slice(packet))
+    }
+
+    /// The general NAK a battery sends for anything it cannot answer.
+    pub fn nak() -> Self {
+        let (bytes, len) = build_packet(ResponseType::Nak as u8, &[]).unwrap_or(([0u8; 64], 0));
+        Self::new(bytes, len)
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+}
+
+/// The packets sent back for one request, in order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Frames {
+    frames: [Frame; 2],
+    count: usize,
+}
+
+impl Frames {
+    pub fn new() -> Self {
+        Self {
+            frames: [Frame::new([0u8; 64], 0); 2],
+            count: 0,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.count = 0;
+    }
+
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &[u8]> {
+        self.frames[..self.count].iter().map(Frame::as_bytes)
+    }
+
+    /// Append a frame, dropped if both slots are taken.
+    pub fn push(&mut self, frame: Frame) {
+        if let Some(slot) = self.frames.get_mut(self.count) {
+            *slot = frame;
+            self.count += 1;
+        }
+    }
+
+    fn push_response(&mut self, code: ResponseType, payload: &[u8]) -> Result<(), Error> {
+        let (bytes, len) = build_packet(code as u8, payload)?;
+        self.push(Frame::new(bytes, len));
+        Ok(())
+    }
+
+    fn push_nak(&mut self) {
+        self.push(Frame::nak());
+    }
+}
+
+impl Default for Frames {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The battery side of the protocol, free of any I/O.
+///
+/// `Responder` maps request frames to response frames and owns everything
+/// that persists between requests: the telemetry profile, the EEPROM and
+/// the authentication state.
+pub struct Responder<E: Eeprom, K: KeyStore = BuiltinKeys, B: BatterySensors = NoSensors> {
+    eeprom: E,
+    profile: BatteryProfile,
+    ciphers: Ciphers<K>,
+    sensors: B,
+    auth_state: AuthState,
+    simulation: Option<Simulation>,
+}
+
+impl<E: Eeprom> Responder<E> {
+    pub fn new(eeprom: E, profile: BatteryProfile) -> Self {
+        Self::with_keys(eeprom, profile, BuiltinKeys)
+    }
+}
+
+impl<E: Eeprom, K: KeyStore> Responder<E, K> {
+    /// Answer the handshake with the key material in `keys` instead of the
+    /// built-in tables.
+    pub fn with_keys(eeprom: E, profile: BatteryProfile, keys: K) -> Self {
+        Self {
+            eeprom,
+            profile,
+            ciphers: Ciphers::new(keys),
+            sensors: NoSensors,
+            auth_state: AuthState::Idle,
+            simulation: None,
+        }
+    }
+}
+
+impl<E: Eeprom, K: KeyStore, B: BatterySensors> Responder<E, K, B> {
+    /// Report the temperature, voltage and current measured by `sensors`.
+    pub fn with_sensors<S: BatterySensors>(self, sensors: S) -> Responder<E, K, S> {
+        Responder {
+            eeprom: self.eeprom,
+            profile: self.profile,
+            ciphers: self.ciphers,
+            sensors,
+            auth_state: self.auth_state,
+            simulation: self.simulation,
+        }
+    }
+
+    pub fn sensors_mut(&mut self) -> &mut B {
+        &mut self.sensors
+    }
+
+    pub fn keys(&self) -> &K {
+        self.ciphers.keys()
+    }
+
+    pub fn profile(&self) -> &BatteryProfile {
+        &self.profile
+    }
+
+    pub fn profile_mut(&mut self) -> &mut BatteryProfile {
+        &mut self.profile
+    }
+
+    pub fn eeprom(&self) -> &E {
+        &self.eeprom
+    }
+
+    pub fn eeprom_mut(&mut self) -> &mut E {
+        &mut self.eeprom
+    }
+
+    pub fn auth_state(&self) -> AuthState {
+        self.auth_state
+    }
+
+    /// Drive the voltage, current, capacity, time left and status readings
+    /// from `simulation` instead of the static profile.
+    pub fn set_simulation(&mut self, simulation: Option<Simulation>) {
+        self.simulation = simulation;
+        self.advance(0);
+    }
+
+    pub fn simulation(&self) -> Option<&Simulation> {
+        self.simulation.as_ref()
+    }
+
+    /// Changes take effect on the next `advance`.
+    pub fn simulation_mut(&mut self) -> Option<&mut Simulation> {
+        self.simulation.as_mut()
+    }
+
+    /// Let `elapsed_ms` pass, the drivers call this while waiting for syscon.
+    pub fn advance(&mut self, elapsed_ms: u32) {
+        if let Some(simulation) = &mut self.simulation {
+            simulation.advance(elapsed_ms);
+            simulation.apply(&mut self.profile);
+        }
+    }
+
+    /// Answer a complete 0x5A packet, header and checksum included.
+    ///
+    /// A packet with a bad checksum is answered with a NAK.
+    pub fn respond_packet(&mut self, packet: &[u8], responses: &mut Frames) -> Result<(), Error> {
+        responses.clear();
+        let [0x5a, length, body @ ..] = packet else {
+            return Err(Error::BadLength)
+        };
+        if *length < 2 || body.len() != *length as usize {
+            return Err(Error::BadLength)
+        }
+        if !verify_checksum(0x5a, *length, body) {
+            responses.push_nak();
+            return Err(Error::Checksum)
+        }
+        self.respond(&body[..body.len() - 1], responses)
+    }
+
+    /// Answer a request. `request` is the command byte followed by its
+    /// arguments, without the header, length and checksum.
+    ///
+    /// `responses` is filled even when an error is returned, a failed
+    /// request is usually still answered.
+    pub fn respond(&mut self, request: &[u8], responses: &mut Frames) -> Result<(), Error> {
+        responses.clear();
+        let Some((&command, args)) = request.split_first() else {
+            return Err(Error::BadLength)
+        };
+
+        let mut profile = self.profile;
+        match command.try_into() {
+            Ok(Commands::CmdReadStatus) => responses.push_response(ResponseType::Ack, &cmd_read_status(&profile)),
+            Ok(Commands::CmdReadTemperature) => {
+                profile.temperature = self.sensors.temperature().unwrap_or(profile.temperature);
+                responses.push_response(ResponseType::Ack, &cmd_read_temperature(&profile))
+            },
+            Ok(Commands::CmdReadVoltage) => {
+                profile.voltage = self.sensors.voltage().unwrap_or(profile.voltage);
+                responses.push_response(ResponseType::Ack, &cmd_read_voltage(&profile))
+            },
+            Ok(Commands::CmdReadCurrent) => {
+                profile.current = self.sensors.current().unwrap_or(profile.current);
+                responses.push_response(ResponseType::Ack, &cmd_read_current(&profile))
+            },
+            Ok(Commands::CmdReadCapacity) => responses.push_response(ResponseType::Ack, &cmd_read_capacity(&profile)),
+            Ok(Commands::CmdRead8) => responses.push_response(ResponseType::Ack, &cmd_read8(&profile)),
+            Ok(Commands::CmdReadTimeLeft) => responses.push_response(ResponseType::Ack, &cmd_read_time_left(&profile)),
+            Ok(Commands::CmdRead11) => responses.push_response(ResponseType::Ack, &cmd_read11(&profile)),
+            Ok(Commands::CmdReadSerialno) => responses.push_response(ResponseType::Ack, &cmd_read_serialno(&profile)),
+            Ok(Commands::CmdRead13) => responses.push_response(ResponseType::Ack, &cmd_read13(&profile)),
+            Ok(Commands::CmdRead22) => responses.push_response(ResponseType::Ack, cmd_read22(&profile)),
+            Ok(Commands::CmdWriteEeprom) => {
+                match cmd_write_eeprom(&mut self.eeprom, args) {
+                    Ok(()) => responses.push_response(ResponseType::Ack, &[]),
+                    Err(e) => {
+                        responses.push_nak();
+                        Err(e)
+                    }
+                }
+            },
+            Ok(Commands::CmdReadEeprom) => {
+                match cmd_read_eeprom(&self.eeprom, args) {
+                    Ok(response) => responses.push_response(ResponseType::Ack, &response),
+                    Err(e) => {
+                        responses.push_nak();
+                        Err(e)
+                    }
+                }
+            },
+            Ok(Commands::CmdAuth1) => self.auth1(args, responses),
+            Ok(Commands::CmdAuth2) => self.auth2(args, responses),
+            Ok(Commands::CmdAuthGo) => self.auth_go(args, responses),
+            _ => {
+                responses.push_nak();
+                info!("Sending General NAK!");
+                Ok(())
+            }
+        }
+    }
+
+    fn auth1(&mut self, args: &[u8], responses: &mut Frames) -> Result<(), Error> {
+        self.auth_state = AuthState::Idle;
+        let Some((&challenge_version, challenge)) = args.split_first() else {
+            responses.push_response(ResponseType::Ack, &[0xff; 8])?;
+            return Err(Error::BadLength)
+        };
+        info!("Challenge version: 0x{:x}", challenge_version);
+        match cmdauth1(&mut self.ciphers, challenge_version, challenge) {
+            Ok((response, challenge1b)) => {
+                self.auth_state = AuthState::Auth1Done { version: challenge_version, challenge1b };
+                responses.push_response(ResponseType::Ack, &response)
+            },
+            Err(e) => {
+                responses.push_response(ResponseType::Ack, &[0xff; 8])?;
+                Err(e)
+            }
+        }
+    }
+
+    fn auth2(&mut self, args: &[u8], responses: &mut Frames) -> Result<(), Error> {
+        let AuthState::Auth1Done { version: challenge_version, challenge1b } = self.auth_state else {
+            info!("CmdAuth2 out of order in state {:?}", self.auth_state);
+            responses.push_nak();
+            return Err(Error::AuthOrder)
+        };
+        let challenge = args.get(1..).unwrap_or(&[]);
+        info!("Challenge version: 0x{:x}", challenge_version);
+        let result = match cmdauth2(&mut self.ciphers, challenge_version, challenge, &challenge1b) {
+            Ok(response) => {
+                self.auth_state = AuthState::Auth2Done { version: challenge_version };
+                responses.push_response(ResponseType::Ack, &response)
+            },
+            Err(e) => Err(e),
+        };
+        if sends_auth2_trailer(challenge_version) {
+            responses.push(Frame::from_slice(&AUTH2_TRAILER));
+        }
+        result
+    }
+
+    fn auth_go(&mut self, args: &[u8], responses: &mut Frames) -> Result<(), Error> {
+        if !matches!(self.auth_state, AuthState::Auth2Done { .. }) {
+            info!("CmdAuthGo out of order in state {:?}", self.auth_state);
+            responses.push_nak();
+            return Err(Error::AuthOrder)
+        }
+        match cmdauthgo(&mut self.ciphers, args) {
+            Ok(response) => {
+                self.auth_state = AuthState::GoDone;
+                responses.push_response(ResponseType::Ack, &response)
+            },
+            Err(e) => {
+                info!("CmdAuthGo returned error: {}", e);
+                Err(e)
+            }
+        }
+    }
+}
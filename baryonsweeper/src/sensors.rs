@@ -0,0 +1,45 @@
+/// Live measurements reported instead of the profile values.
+///
+/// Each reading is taken when syscon asks for it. Returning `None`, e.g.
+/// when the board has no such sensor or a conversion failed, falls back to
+/// the profile or simulation value.
+pub trait BatterySensors {
+    /// `CmdReadTemperature`, in °C.
+    fn temperature(&mut self) -> Option<u8>;
+    /// `CmdReadVoltage`, in mV.
+    fn voltage(&mut self) -> Option<u16>;
+    /// `CmdReadCurrent`, in mA.
+    fn current(&mut self) -> Option<i16>;
+}
+
+/// No sensors, every reading comes from the profile.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct NoSensors;
+
+impl BatterySensors for NoSensors {
+    fn temperature(&mut self) -> Option<u8> {
+        None
+    }
+
+    fn voltage(&mut self) -> Option<u16> {
+        None
+    }
+
+    fn current(&mut self) -> Option<i16> {
+        None
+    }
+}
+
+impl<B: BatterySensors + ?Sized> BatterySensors for &mut B {
+    fn temperature(&mut self) -> Option<u8> {
+        (**self).temperature()
+    }
+
+    fn voltage(&mut self) -> Option<u16> {
+        (**self).voltage()
+    }
+
+    fn current(&mut self) -> Option<i16> {
+        (**self).current()
+    }
+}
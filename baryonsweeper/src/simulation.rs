@@ -0,0 +1,159 @@
+use crate::BatteryProfile;
+
+/// mA·ms in one mAh.
+const MA_MS_PER_MAH: u64 = 3_600_000;
+
+/// Open circuit voltage of a single Li-ion cell against state of charge,
+/// as (percent, mV) points.
+const DISCHARGE_CURVE: [(u8, u16); 8] = [
+    (0, 3300),
+    (5, 3500),
+    (10, 3600),
+    (20, 3680),
+    (40, 3760),
+    (60, 3850),
+    (80, 3980),
+    (100, 4150),
+];
+
+/// Rise above the open circuit voltage while charging.
+const CHARGING_OFFSET_MV: u16 = 50;
+
+/// Time driven charge model behind the voltage, current, capacity and
+/// time left readings.
+///
+/// Current keeps the sign of the default profile's reading: positive while
+/// the pack feeds the console, negative while it is charged.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Simulation {
+    full_capacity: u16,
+    /// Remaining charge, in mA·ms.
+    charge: u64,
+    load_current: u16,
+    charge_current: u16,
+    charging: bool,
+    status: [u8; 3],
+    charging_status: [u8; 3],
+}
+
+impl Simulation {
+    /// Start from a full pack of `profile.capacity` mAh, drawing
+    /// `load_current` mA.
+    ///
+    /// The status bytes of a charging pack have not been captured yet, so
+    /// by default charging reports `profile.status` too; see
+    /// `with_charging_status`.
+    pub fn new(profile: &BatteryProfile, load_current: u16) -> Self {
+        Self {
+            full_capacity: profile.capacity,
+            charge: profile.capacity as u64 * MA_MS_PER_MAH,
+            load_current,
+            charge_current: 1000,
+            charging: false,
+            status: profile.status,
+            charging_status: profile.status,
+        }
+    }
+
+    /// Start at `percent` of the full capacity instead.
+    pub fn with_level(mut self, percent: u8) -> Self {
+        self.charge = self.full_charge() * percent.min(100) as u64 / 100;
+        self
+    }
+
+    pub fn with_charge_current(mut self, charge_current: u16) -> Self {
+        self.charge_current = charge_current;
+        self
+    }
+
+    /// `CmdReadStatus` bytes reported while charging.
+    pub fn with_charging_status(mut self, status: [u8; 3]) -> Self {
+        self.charging_status = status;
+        self
+    }
+
+    pub fn set_load_current(&mut self, load_current: u16) {
+        self.load_current = load_current;
+    }
+
+    /// Whether the console is feeding the pack, e.g. read from a GPIO
+    /// watching the charger.
+    pub fn set_charging(&mut self, charging: bool) {
+        self.charging = charging;
+    }
+
+    pub fn is_charging(&self) -> bool {
+        self.charging
+    }
+
+    /// Remaining capacity in mAh.
+    pub fn capacity(&self) -> u16 {
+        (self.charge / MA_MS_PER_MAH) as u16
+    }
+
+    /// State of charge in percent.
+    pub fn level(&self) -> u8 {
+        match self.full_charge() {
+            0 => 0,
+            full => (self.charge * 100 / full) as u8,
+        }
+    }
+
+    /// Let `elapsed_ms` pass at the current load or charge current.
+    pub fn advance(&mut self, elapsed_ms: u32) {
+        if self.charging {
+            let added = self.charge_current as u64 * elapsed_ms as u64;
+            self.charge = (self.charge + added).min(self.full_charge());
+        } else {
+            let used = self.load_current as u64 * elapsed_ms as u64;
+            self.charge = self.charge.saturating_sub(used);
+        }
+    }
+
+    /// Write the simulated readings into `profile`.
+    pub fn apply(&self, profile: &mut BatteryProfile) {
+        let open_circuit = voltage_at(self.level());
+        profile.capacity = self.capacity();
+        if self.charging {
+            profile.status = self.charging_status;
+            profile.voltage = open_circuit.saturating_add(CHARGING_OFFSET_MV);
+            profile.current = -(self.charge_current.min(i16::MAX as u16) as i16);
+            let missing = (self.full_charge() - self.charge) / MA_MS_PER_MAH;
+            profile.time_left = minutes(missing, self.charge_current);
+        } else {
+            profile.status = self.status;
+            profile.voltage = open_circuit;
+            profile.current = self.load_current.min(i16::MAX as u16) as i16;
+            profile.time_left = minutes(self.capacity() as u64, self.load_current);
+        }
+    }
+
+    fn full_charge(&self) -> u64 {
+        self.full_capacity as u64 * MA_MS_PER_MAH
+    }
+}
+
+/// Minutes to move `mah` at `current` mA, saturated to the 16-bit field.
+fn minutes(mah: u64, current: u16) -> u16 {
+    match current {
+        0 => u16::MAX,
+        current => (mah * 60 / current as u64).min(u16::MAX as u64) as u16,
+    }
+}
+
+/// Interpolate `DISCHARGE_CURVE`.
+fn voltage_at(percent: u8) -> u16 {
+    let mut lower = DISCHARGE_CURVE[0];
+    for upper in DISCHARGE_CURVE {
+        if percent <= upper.0 {
+            let span = (upper.0 - lower.0) as u32;
+            if span == 0 {
+                return upper.1
+            }
+            let rise = (upper.1 - lower.1) as u32 * (percent - lower.0) as u32 / span;
+            return lower.1 + rise as u16
+        }
+        lower = upper;
+    }
+    lower.1
+}
@@ -0,0 +1,282 @@
+use aes::Aes128;
+use aes::cipher::{KeyIvInit, BlockEncryptMut, generic_array::GenericArray};
+use rand_core::RngCore;
+
+use crate::cipher::Ciphers;
+use crate::responder::{sends_auth2_trailer, AUTH2_TRAILER};
+use crate::{
+    challenge2, cmdauth1, encrypt_bytes, BatteryProfile, BuiltinKeys, EmulatedEeprom, Error, Frame,
+    Frames, KeyStore, Request, Response, EEPROM_SIZE,
+};
+
+use log::info;
+
+/// Fixed first half of the `CmdAuthGo` request.
+const GO_REQUEST_HEADER: [u8; 8] = [0x20, 0x10, 0x00, 0x06, 0x82, 0x82, 0x82, 0x82];
+
+/// Fixed first half of the `CmdAuthGo` response.
+const GO_RESPONSE_HEADER: [u8; 8] = [0x20, 0x01, 0x00, 0x00, 0x82, 0x82, 0x82, 0x82];
+
+/// Telemetry requests sent once the battery is authenticated.
+const TELEMETRY: [Request; 9] = [
+    Request::ReadTemperature,
+    Request::ReadVoltage,
+    Request::ReadCurrent,
+    Request::ReadCapacity,
+    Request::Read8,
+    Request::ReadTimeLeft,
+    Request::Read11,
+    Request::Read13,
+    Request::Read22,
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Step {
+    Idle,
+    Auth1Sent { challenge: [u8; 8], expected: [u8; 16], challenge1b: [u8; 16] },
+    Auth1Verified { challenge1b: [u8; 16] },
+    Auth2Sent { challenge: [u8; 8], expected: [u8; 16] },
+    Auth2Verified,
+    AuthGoSent { data: [u8; 40], nonce: [u8; 16] },
+    Authenticated,
+}
+
+/// The console side of the protocol, free of any I/O.
+///
+/// Generates the challenges syscon sends for one challenge version and
+/// checks the answers against the key tables, so it can authenticate both
+/// our emulators and real batteries.
+pub struct SysconEmulator<K: KeyStore = BuiltinKeys> {
+    version: u8,
+    ciphers: Ciphers<K>,
+    auth_go: bool,
+    step: Step,
+}
+
+impl SysconEmulator {
+    pub fn new(version: u8) -> Result<Self, Error> {
+        Self::with_keys(version, BuiltinKeys)
+    }
+}
+
+impl<K: KeyStore> SysconEmulator<K> {
+    pub fn with_keys(version: u8, keys: K) -> Result<Self, Error> {
+        let known = keys.secret1(version).is_some()
+            && keys.secret2(version).is_some()
+            && keys.key(version).is_some();
+        if !known {
+            return Err(Error::UnknownChallengeVersion(version))
+        }
+        Ok(Self { version, ciphers: Ciphers::new(keys), auth_go: false, step: Step::Idle })
+    }
+
+    /// Follow `CmdAuth2` with the `CmdAuthGo` handshake, like a PSP Go.
+    pub fn with_auth_go(mut self, auth_go: bool) -> Self {
+        self.auth_go = auth_go;
+        self
+    }
+
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    /// Whether the last handshake completed and every answer checked out.
+    pub fn is_authenticated(&self) -> bool {
+        self.step == Step::Authenticated
+    }
+
+    pub fn auth1_request<R: RngCore>(&mut self, rng: &mut R) -> Result<Frame, Error> {
+        self.step = Step::Idle;
+        let mut challenge = [0u8; 8];
+        rng.fill_bytes(&mut challenge);
+        let (expected, challenge1b) = cmdauth1(&mut self.ciphers, self.version, &challenge)?;
+        self.step = Step::Auth1Sent { challenge, expected, challenge1b };
+        Request::Auth1 { version: self.version, challenge }.to_frame()
+    }
+
+    pub fn verify_auth1(&mut self, responses: &Frames) -> Result<(), Error> {
+        let Step::Auth1Sent { challenge, expected, challenge1b } = self.step else {
+            return Err(Error::AuthOrder)
+        };
+        self.step = Step::Idle;
+        let request = Request::Auth1 { version: self.version, challenge };
+        if first_answer(&request, responses)? != Response::Auth1(expected) {
+            return Err(Error::BadResponse)
+        }
+        self.step = Step::Auth1Verified { challenge1b };
+        Ok(())
+    }
+
+    pub fn auth2_request(&mut self) -> Result<Frame, Error> {
+        let Step::Auth1Verified { challenge1b } = self.step else {
+            return Err(Error::AuthOrder)
+        };
+        let challenge2 = challenge2(&mut self.ciphers, self.version, &challenge1b)?;
+        let mut expected = [0u8; 16];
+        encrypt_bytes(&mut self.ciphers, &challenge2, self.version, &mut expected)?;
+        let mut challenge = [0u8; 8];
+        challenge.copy_from_slice(&challenge2[..8]);
+        self.step = Step::Auth2Sent { challenge, expected };
+        Request::Auth2 { challenge }.to_frame()
+    }
+
+    pub fn verify_auth2(&mut self, responses: &Frames) -> Result<(), Error> {
+        let Step::Auth2Sent { challenge, expected } = self.step else {
+            return Err(Error::AuthOrder)
+        };
+        self.step = Step::Idle;
+        if first_answer(&Request::Auth2 { challenge }, responses)? != Response::Auth2(expected) {
+            return Err(Error::BadResponse)
+        }
+        if sends_auth2_trailer(self.version) && responses.iter().nth(1) != Some(&AUTH2_TRAILER[..]) {
+            return Err(Error::BadResponse)
+        }
+        self.step = if self.auth_go { Step::Auth2Verified } else { Step::Authenticated };
+        Ok(())
+    }
+
+    pub fn auth_go_request<R: RngCore>(&mut self, rng: &mut R) -> Result<Frame, Error> {
+        if self.step != Step::Auth2Verified {
+            return Err(Error::AuthOrder)
+        }
+        let mut nonce = [0u8; 16];
+        rng.fill_bytes(&mut nonce);
+        let blocks = cbc_encrypt(&self.ciphers.keys().go_key1(), [nonce, self.ciphers.keys().go_secret()]);
+
+        let mut data = [0u8; 40];
+        data[0..8].copy_from_slice(&GO_REQUEST_HEADER);
+        data[8..24].copy_from_slice(&blocks[0]);
+        data[24..40].copy_from_slice(&blocks[1]);
+        self.step = Step::AuthGoSent { data, nonce };
+        Request::AuthGo { data }.to_frame()
+    }
+
+    pub fn verify_auth_go(&mut self, responses: &Frames) -> Result<(), Error> {
+        let Step::AuthGoSent { data, nonce } = self.step else {
+            return Err(Error::AuthOrder)
+        };
+        self.step = Step::Idle;
+        let Response::AuthGo(payload) = first_answer(&Request::AuthGo { data }, responses)? else {
+            return Err(Error::BadResponse)
+        };
+        if payload[0..8] != GO_RESPONSE_HEADER {
+            return Err(Error::BadResponse)
+        }
+        let mut blocks = [[0u8; 16]; 2];
+        blocks[0].copy_from_slice(&payload[8..24]);
+        blocks[1].copy_from_slice(&payload[24..40]);
+        // the battery decrypts the swapped nonce, encrypting undoes it
+        let mut swapped = [0u8; 16];
+        swapped[0..8].copy_from_slice(&nonce[8..16]);
+        swapped[8..16].copy_from_slice(&nonce[0..8]);
+        if cbc_encrypt(&self.ciphers.keys().go_key2(), blocks) != [swapped, [0u8; 16]] {
+            return Err(Error::BadResponse)
+        }
+        self.step = Step::Authenticated;
+        Ok(())
+    }
+
+    /// Run one full polling cycle the way syscon does after the battery is
+    /// plugged in: status, serial number, the handshake and then the
+    /// telemetry, which is returned as a profile.
+    ///
+    /// `exchange` sends a request and replaces the contents of `responses`
+    /// with the battery's answer.
+    pub fn poll<R, X>(&mut self, rng: &mut R, mut exchange: X) -> Result<BatteryProfile, Error>
+    where
+        R: RngCore,
+        X: FnMut(&Frame, &mut Frames) -> Result<(), Error>,
+    {
+        let mut profile = BatteryProfile::new();
+        let mut responses = Frames::new();
+
+        for request in [Request::ReadStatus, Request::ReadSerialno] {
+            exchange(&request.to_frame()?, &mut responses)?;
+            store(&first_answer(&request, &responses)?, &mut profile)?;
+        }
+
+        exchange(&self.auth1_request(rng)?, &mut responses)?;
+        self.verify_auth1(&responses)?;
+        exchange(&self.auth2_request()?, &mut responses)?;
+        self.verify_auth2(&responses)?;
+        if self.auth_go {
+            exchange(&self.auth_go_request(rng)?, &mut responses)?;
+            self.verify_auth_go(&responses)?;
+        }
+        info!("Battery authenticated with version 0x{:02x}", self.version);
+
+        for request in &TELEMETRY {
+            exchange(&request.to_frame()?, &mut responses)?;
+            store(&first_answer(request, &responses)?, &mut profile)?;
+        }
+        Ok(profile)
+    }
+
+    /// Read one byte of the battery EEPROM.
+    pub fn read_eeprom<X>(&mut self, address: u8, mut exchange: X) -> Result<u8, Error>
+    where
+        X: FnMut(&Frame, &mut Frames) -> Result<(), Error>,
+    {
+        let request = Request::ReadEeprom { address };
+        let mut responses = Frames::new();
+        exchange(&request.to_frame()?, &mut responses)?;
+        match first_answer(&request, &responses)? {
+            Response::Eeprom(value) => Ok(value),
+            _ => Err(Error::BadResponse),
+        }
+    }
+
+    /// Read the whole battery EEPROM, e.g. to clone it onto the emulator.
+    pub fn dump_eeprom<X>(&mut self, mut exchange: X) -> Result<EmulatedEeprom, Error>
+    where
+        X: FnMut(&Frame, &mut Frames) -> Result<(), Error>,
+    {
+        let mut contents = [0u8; EEPROM_SIZE];
+        for (address, byte) in contents.iter_mut().enumerate() {
+            *byte = self.read_eeprom(address as u8, &mut exchange)?;
+        }
+        Ok(EmulatedEeprom::new(contents))
+    }
+}
+
+/// Decode the first response to `request`.
+fn first_answer(request: &Request, responses: &Frames) -> Result<Response, Error> {
+    let Some(response) = responses.iter().next() else {
+        return Err(Error::BadResponse)
+    };
+    answer(request, response)
+}
+
+/// Decode a 0xA5 response to `request`, turning a NAK into an error.
+pub(crate) fn answer(request: &Request, response: &[u8]) -> Result<Response, Error> {
+    match Response::from_frame(request, response)? {
+        Response::Nak => Err(Error::Nak),
+        response => Ok(response),
+    }
+}
+
+/// Store a status, identity or telemetry answer in `profile`.
+pub(crate) fn store(response: &Response, profile: &mut BatteryProfile) -> Result<(), Error> {
+    match response {
+        Response::Status(status) => profile.status = *status,
+        Response::Temperature(temperature) => profile.temperature = *temperature,
+        Response::Voltage(voltage) => profile.voltage = *voltage,
+        Response::Current(current) => profile.current = *current,
+        Response::Capacity(capacity) => profile.capacity = *capacity,
+        Response::Read8(read8) => profile.read8 = *read8,
+        Response::TimeLeft(time_left) => profile.time_left = *time_left,
+        Response::Read11(read11) => profile.read11 = *read11,
+        Response::Serialno(serial_number) => profile.serial_number = *serial_number,
+        Response::Read13(read13) => profile.read13 = *read13,
+        Response::Read22(manufacturer) => *profile = profile.with_manufacturer(manufacturer),
+        _ => return Err(Error::BadResponse),
+    }
+    Ok(())
+}
+
+fn cbc_encrypt(key: &[u8; 16], plain: [[u8; 16]; 2]) -> [[u8; 16]; 2] {
+    let mut encryptor = cbc::Encryptor::<Aes128>::new(&GenericArray::from(*key), &GenericArray::from([0u8; 16]));
+    let mut blocks = plain.map(GenericArray::from);
+    encryptor.encrypt_blocks_mut(&mut blocks);
+    blocks.map(Into::into)
+}
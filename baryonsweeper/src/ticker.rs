@@ -0,0 +1,18 @@
+use embedded_hal::timer::{CountDown, Periodic};
+
+/// The ticker of a `BaryonSweeper` that has none. It never expires, so
+/// the battery simulation stands still.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct NoTicker;
+
+impl CountDown for NoTicker {
+    type Time = core::time::Duration;
+
+    fn start<T: Into<Self::Time>>(&mut self, _count: T) {}
+
+    fn wait(&mut self) -> nb::Result<(), void::Void> {
+        Err(nb::Error::WouldBlock)
+    }
+}
+
+impl Periodic for NoTicker {}
@@ -0,0 +1,65 @@
+/// Duration handed to the `CountDown` of the blocking drivers.
+///
+/// `Passthrough` and the `BaryonSweeper` ticker run a timer in short ticks
+/// of their own, and `Passthrough` counts the timeout it is given in those
+/// ticks, so they need to convert from and to milliseconds. Implemented
+/// for the `fugit` durations the HALs use and for `core::time::Duration`.
+pub trait Timeout: Clone {
+    fn millis(ms: u32) -> Self;
+
+    /// Whole milliseconds, saturated to `u32::MAX`.
+    fn to_millis(&self) -> u32;
+}
+
+/// Saturates at `u32::MAX` ticks, about 4.29 s for nanosecond durations.
+impl<const NOM: u32, const DENOM: u32> Timeout for fugit::Duration<u32, NOM, DENOM> {
+    fn millis(ms: u32) -> Self {
+        Self::from_ticks(u32::try_from(ticks(ms, NOM, DENOM)).unwrap_or(u32::MAX))
+    }
+
+    fn to_millis(&self) -> u32 {
+        millis(self.ticks().into(), NOM, DENOM)
+    }
+}
+
+impl<const NOM: u32, const DENOM: u32> Timeout for fugit::Duration<u64, NOM, DENOM> {
+    fn millis(ms: u32) -> Self {
+        Self::from_ticks(ticks(ms, NOM, DENOM))
+    }
+
+    fn to_millis(&self) -> u32 {
+        millis(self.ticks(), NOM, DENOM)
+    }
+}
+
+impl Timeout for core::time::Duration {
+    fn millis(ms: u32) -> Self {
+        core::time::Duration::from_millis(ms.into())
+    }
+
+    fn to_millis(&self) -> u32 {
+        u32::try_from(self.as_millis()).unwrap_or(u32::MAX)
+    }
+}
+
+#[cfg(feature="test")]
+impl Timeout for embedded_time::duration::Milliseconds {
+    fn millis(ms: u32) -> Self {
+        embedded_time::duration::Milliseconds(ms)
+    }
+
+    fn to_millis(&self) -> u32 {
+        self.0
+    }
+}
+
+/// `ms` in ticks of `NOM / DENOM` seconds.
+fn ticks(ms: u32, nom: u32, denom: u32) -> u64 {
+    ms as u64 * denom as u64 / (nom as u64 * 1000)
+}
+
+/// `ticks` of `NOM / DENOM` seconds in milliseconds.
+fn millis(ticks: u64, nom: u32, denom: u32) -> u32 {
+    let ms = ticks as u128 * nom as u128 * 1000 / denom as u128;
+    u32::try_from(ms).unwrap_or(u32::MAX)
+}
@@ -1,12 +1,15 @@
 #![cfg_attr(not(feature="std"), no_std)]
 
+// shared by the backends, which can be enabled together
+#[cfg(any(feature="usb", feature="std"))]
+use log::{Level, Record, Metadata};
+
 cfg_if::cfg_if! {
     if #[cfg(feature="usb")] {
 
         use core::fmt::Write;
         use critical_section::Mutex;
         use core::cell::RefCell;
-        use log::{Level, Record, Metadata};
 
         pub struct UsbLogger<'a, U, const N: usize>
         where U: usb_device::bus::UsbBus
@@ -75,7 +78,7 @@ cfg_if::cfg_if! {
     if #[cfg(feature="std")] {
         static LOGGER: StdLogger = StdLogger{};
 
-        use log::{Level, Record, LevelFilter, SetLoggerError, Metadata};
+        use log::{LevelFilter, SetLoggerError};
 
         pub struct StdLogger {}
         impl StdLogger {